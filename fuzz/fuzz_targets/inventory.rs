@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mori::bot::inventory::Inventory;
+
+fuzz_target!(|data: &[u8]| {
+    let mut inventory = Inventory::new();
+    if inventory.parse(data).is_ok() {
+        assert_eq!(inventory.items.len(), inventory.item_count as usize);
+    }
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mori::types::e_tank_packet_type::ETankPacketType;
+use mori::types::tank_packet_type::TankPacketType;
+use mori::utils::variant::VariantList;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(pkt) = TankPacketType::deserialize(data) {
+        assert_eq!(pkt.extended_data.len(), pkt.extended_data_length as usize);
+        let serialized = pkt.serialize();
+        let reparsed = TankPacketType::deserialize(&serialized).expect("serialized packet didn't parse");
+        assert_eq!(reparsed.serialize(), serialized);
+
+        // Call functions carry a variant list, that's what the handlers read next
+        if pkt.packet_type == ETankPacketType::NetGamePacketCallFunction {
+            let _ = VariantList::deserialize(&pkt.extended_data);
+        }
+    }
+});
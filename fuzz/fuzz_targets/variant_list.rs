@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mori::utils::variant::VariantList;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(variants) = VariantList::deserialize(data) {
+        // Whatever parses has to come back the same after a round trip
+        // A parsed list never has more than 255 variants, so it always serializes
+        let serialized = variants.serialize().expect("parsed list didn't serialize");
+        let reparsed = VariantList::deserialize(&serialized).expect("serialized list didn't parse");
+        assert_eq!(reparsed.serialize().unwrap(), serialized);
+    }
+});
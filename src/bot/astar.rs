@@ -1,12 +1,10 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, sync::Arc};
 
 use gtitem_r::structs::ItemDatabase;
-use gtworld_r::Tile;
+use gtworld_r::World;
 
-use super::Bot;
+// Collision type of blocks that can't be walked through
+const SOLID: u8 = 1;
 
 pub struct AStar {
     pub width: u32,
@@ -47,23 +45,35 @@ impl AStar {
         }
     }
 
-    pub fn update(&mut self, bot_mutex: &Arc<Mutex<Bot>>) {
-        let bot = bot_mutex.lock().unwrap();
-        self.width = bot.world.width;
-        self.height = bot.world.height;
-        for i in 0..bot.world.tiles.len() {
+    pub fn update(&mut self, world: &World) {
+        self.width = world.width;
+        self.height = world.height;
+        self.grid.clear();
+        for i in 0..world.tiles.len() {
             let mut node = Node::new();
-            node.x = (i as u32) % bot.world.width;
-            node.y = (i as u32) / bot.world.width;
-            let item = self
-                .item_database
-                .get_item(&(bot.world.tiles[i].foreground_item_id as u32))
-                .unwrap();
-            node.collision_type = item.collision_type;
+            node.x = (i as u32) % world.width;
+            node.y = (i as u32) / world.width;
+            node.collision_type = self.collision_type(world.tiles[i].foreground_item_id);
             self.grid.push(node);
         }
     }
 
+    // Patches one node after a tile changed, the rest of the grid stays as it is
+    pub fn update_tile(&mut self, x: u32, y: u32, foreground_item_id: u16) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let collision_type = self.collision_type(foreground_item_id);
+        self.grid[(y * self.width + x) as usize].collision_type = collision_type;
+    }
+
+    // An item missing from items.dat (e.g. an outdated one) is treated as a wall, not walked into
+    fn collision_type(&self, item_id: u16) -> u8 {
+        self.item_database
+            .get_item(&(item_id as u32))
+            .map_or(SOLID, |item| item.collision_type)
+    }
+
     pub fn find_path(&self, from_x: u32, from_y: u32, to_x: u32, to_y: u32) -> Option<Vec<Node>> {
         let mut open_list: Vec<Node> = Vec::new();
         let mut closed_list: Vec<Node> = Vec::new();
@@ -153,7 +163,7 @@ impl AStar {
                 let index = (new_y as u32 * self.width + new_x as u32) as usize;
                 let neighbor = &self.grid[index];
 
-                if neighbor.collision_type != 1 {
+                if neighbor.collision_type != SOLID {
                     neighbors.push(neighbor.clone());
                 }
             }
@@ -191,3 +201,45 @@ impl AStar {
         path
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 3;
+
+    // Every node starts walkable, the database knows no items
+    fn open_grid() -> AStar {
+        let mut astar = AStar::new(Arc::new(ItemDatabase::new()));
+        astar.width = SIZE;
+        astar.height = SIZE;
+        astar.grid = (0..SIZE * SIZE)
+            .map(|i| {
+                let mut node = Node::new();
+                node.x = i % SIZE;
+                node.y = i / SIZE;
+                node
+            })
+            .collect();
+        astar
+    }
+
+    #[test]
+    fn unknown_item_is_solid() {
+        let mut astar = open_grid();
+        astar.update_tile(1, 1, 9999);
+        assert_eq!(astar.grid[4].collision_type, SOLID);
+        assert!(astar.find_path(0, 0, 1, 1).is_none());
+    }
+
+    #[test]
+    fn paths_around_unknown_items() {
+        let mut astar = open_grid();
+        for y in 0..2 {
+            astar.update_tile(1, y, 9999);
+        }
+        let path = astar.find_path(0, 0, 2, 0).unwrap();
+        assert!(path.iter().all(|node| node.collision_type != SOLID));
+        assert!(path.iter().any(|node| node.y == 2));
+    }
+}
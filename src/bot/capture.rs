@@ -0,0 +1,291 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use enet::PeerID;
+use spdlog::{error, warn};
+
+// Start of every capture file, bump the version if the record layout changes
+const MAGIC: &[u8; 8] = b"MORICAP1";
+// Packets kept in memory for the inspector, the oldest are dropped first
+const LOG_SIZE: usize = 1000;
+
+thread_local! {
+    // Taps of the peers on this reactor, sends only know their PeerID
+    static TAPS: RefCell<HashMap<PeerID, Tap>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    // Since the recorder was created
+    pub elapsed: Duration,
+    pub direction: Direction,
+    // The whole ENet payload, packet type included
+    pub data: Vec<u8>,
+}
+
+// Writes every payload a bot sends and receives to a file, one record after another:
+// elapsed micros (u64), direction (u8, 0 = inbound), length (u32), payload. All little endian.
+pub struct Recorder {
+    path: String,
+    started_at: Instant,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> io::Result<Recorder> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.flush()?;
+        Ok(Recorder {
+            path: path.to_string(),
+            started_at: Instant::now(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        let elapsed = self.started_at.elapsed().as_micros() as u64;
+        let mut writer = self.writer.lock().unwrap();
+        let result = (|| {
+            writer.write_all(&elapsed.to_le_bytes())?;
+            writer.write_all(&[direction as u8])?;
+            writer.write_all(&(data.len() as u32).to_le_bytes())?;
+            writer.write_all(data)?;
+            // Flushed right away, the capture is most useful when the bot is about to crash
+            writer.flush()
+        })();
+        if let Err(err) = result {
+            error!("Failed to write to capture {}: {}", self.path, err);
+        }
+    }
+}
+
+// The last packets of a bot, always on.
+// Records are shared so the GUI can take a cheap snapshot every frame.
+pub struct PacketLog {
+    started_at: Instant,
+    paused: AtomicBool,
+    entries: Mutex<LogEntries>,
+}
+
+struct LogEntries {
+    next_id: u64,
+    records: VecDeque<(u64, Arc<Record>)>,
+}
+
+impl Default for PacketLog {
+    fn default() -> PacketLog {
+        PacketLog::new()
+    }
+}
+
+impl PacketLog {
+    pub fn new() -> PacketLog {
+        PacketLog {
+            started_at: Instant::now(),
+            paused: AtomicBool::new(false),
+            entries: Mutex::new(LogEntries {
+                next_id: 0,
+                records: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        if self.is_paused() {
+            return;
+        }
+        let record = Record {
+            elapsed: self.started_at.elapsed(),
+            direction,
+            data: data.to_vec(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.records.len() == LOG_SIZE {
+            entries.records.pop_front();
+        }
+        let id = entries.next_id;
+        entries.next_id += 1;
+        entries.records.push_back((id, Arc::new(record)));
+    }
+
+    // Oldest first, ids stay the same while a record is in the log
+    pub fn snapshot(&self) -> Vec<(u64, Arc<Record>)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .records
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().records.clear();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+}
+
+// Where the traffic of a peer goes besides the network
+#[derive(Clone)]
+pub struct Tap {
+    pub recorder: Option<Arc<Recorder>>,
+    pub log: Arc<PacketLog>,
+}
+
+pub fn read(path: &str) -> io::Result<Vec<Record>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    if !data.starts_with(MAGIC) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a capture file",
+        ));
+    }
+
+    let mut records = Vec::new();
+    let mut rest = &data[MAGIC.len()..];
+    while !rest.is_empty() {
+        // A capture cut off mid-record (e.g. the process died) still replays up to there
+        if rest.len() < 13 {
+            warn!(
+                "{} ends in a partial record header, {} bytes skipped",
+                path,
+                rest.len()
+            );
+            break;
+        }
+        let elapsed = u64::from_le_bytes(rest[0..8].try_into().unwrap());
+        let direction = match rest[8] {
+            0 => Direction::Inbound,
+            _ => Direction::Outbound,
+        };
+        let len = u32::from_le_bytes(rest[9..13].try_into().unwrap()) as usize;
+        if rest.len() < 13 + len {
+            warn!(
+                "{} ends in a partial {} byte record, {} bytes skipped",
+                path,
+                len,
+                rest.len()
+            );
+            break;
+        }
+        records.push(Record {
+            elapsed: Duration::from_micros(elapsed),
+            direction,
+            data: rest[13..13 + len].to_vec(),
+        });
+        rest = &rest[13 + len..];
+    }
+    Ok(records)
+}
+
+// Called by the reactor for each of its peers, keeps the tap of a peer in line with its bot
+pub fn track(peer_id: PeerID, tap: Option<Tap>) {
+    TAPS.with_borrow_mut(|taps| match tap {
+        Some(tap) => {
+            taps.insert(peer_id, tap);
+        }
+        None => {
+            taps.remove(&peer_id);
+        }
+    });
+}
+
+pub fn record(peer_id: PeerID, direction: Direction, data: &[u8]) {
+    TAPS.with_borrow(|taps| {
+        if let Some(tap) = taps.get(&peer_id) {
+            tap.log.record(direction, data);
+            if let Some(recorder) = &tap.recorder {
+                recorder.record(direction, data);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh file per test, they run in parallel
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("mori-{}-{}", std::process::id(), name))
+            .join("test.cap")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn round_trips_records() {
+        let path = temp_path("round-trip");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(Direction::Outbound, &[2, 0, 0, 0, b'h', b'i']);
+        recorder.record(Direction::Inbound, &[4, 0, 0, 0]);
+        recorder.record(Direction::Inbound, &[]);
+
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].direction, Direction::Outbound);
+        assert_eq!(records[0].data, vec![2, 0, 0, 0, b'h', b'i']);
+        assert_eq!(records[1].direction, Direction::Inbound);
+        assert_eq!(records[1].data, vec![4, 0, 0, 0]);
+        assert!(records[2].data.is_empty());
+        assert!(records[0].elapsed <= records[1].elapsed);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_records_before_a_cut_off_tail() {
+        let path = temp_path("truncated");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(Direction::Inbound, &[1, 2, 3, 4]);
+        recorder.record(Direction::Outbound, &[5, 6, 7, 8, 9]);
+        let data = fs::read(&path).unwrap();
+        // The first record ends after the magic, its header and its payload
+        let first_end = MAGIC.len() + 13 + 4;
+
+        for len in first_end..data.len() {
+            fs::write(&path, &data[..len]).unwrap();
+            let records = read(&path).unwrap();
+            assert_eq!(records.len(), 1, "cut to {}", len);
+            assert_eq!(records[0].data, vec![1, 2, 3, 4]);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_path("not-a-capture");
+        fs::create_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+        fs::write(&path, b"MORICAP0").unwrap();
+        assert_eq!(read(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use spdlog::info;
+
+use crate::types::bot_state::BotState;
+
+use super::item_actions::{ItemAction, ItemActionResult};
+use super::{
+    collect, collect_all, disconnect, find_path, item_action, pick_up_in_range, place, punch, talk,
+    walk, warp, Bot,
+};
+
+// Commands are queued on the bot and executed by its network thread, so they can be issued from any thread.
+// Packet handlers queue them too, since they run with the bot locked and the actions lock it again.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Warp(String),
+    Talk(String),
+    Walk {
+        x: f32,
+        y: f32,
+        ap: bool,
+    },
+    Punch {
+        offset_x: i32,
+        offset_y: i32,
+    },
+    Place {
+        offset_x: i32,
+        offset_y: i32,
+        block_id: u32,
+    },
+    FindPath {
+        x: u32,
+        y: u32,
+    },
+    // A dropped object by uid
+    Collect(u32),
+    // Queued by Collect after the walk there, picks the object up without walking again
+    PickUp(u32),
+    // Every dropped object within the radius, in tiles
+    CollectAll {
+        radius: f32,
+    },
+    // The reply gets how many are left once the server confirmed, see Manager::drop_item
+    Drop {
+        item_id: u16,
+        amount: u8,
+        reply: Option<Sender<ItemActionResult>>,
+    },
+    Trash {
+        item_id: u16,
+        amount: u8,
+        reply: Option<Sender<ItemActionResult>>,
+    },
+    Disconnect,
+}
+
+pub fn handle(bot_mutex: &Arc<Mutex<Bot>>, command: Command) {
+    let peer_id = match bot_mutex.lock().unwrap().peer_id {
+        Some(peer_id) => peer_id,
+        None => {
+            info!("Dropping command {:?}, bot is not connected", command);
+            return;
+        }
+    };
+
+    match command {
+        Command::Warp(world) => warp(peer_id, &world),
+        Command::Talk(message) => talk(peer_id, &message),
+        Command::Walk { x, y, ap } => walk(bot_mutex, peer_id, x, y, ap),
+        Command::Punch { offset_x, offset_y } => punch(bot_mutex, peer_id, offset_x, offset_y),
+        Command::Place {
+            offset_x,
+            offset_y,
+            block_id,
+        } => place(bot_mutex, peer_id, offset_x, offset_y, block_id),
+        Command::FindPath { x, y } => find_path(bot_mutex, x, y),
+        Command::Collect(uid) => collect(bot_mutex, peer_id, uid),
+        Command::PickUp(uid) => pick_up_in_range(bot_mutex, peer_id, uid),
+        Command::CollectAll { radius } => collect_all(bot_mutex, radius),
+        Command::Drop {
+            item_id,
+            amount,
+            reply,
+        } => item_action(bot_mutex, peer_id, ItemAction::Drop, item_id, amount, reply),
+        Command::Trash {
+            item_id,
+            amount,
+            reply,
+        } => item_action(
+            bot_mutex,
+            peer_id,
+            ItemAction::Trash,
+            item_id,
+            amount,
+            reply,
+        ),
+        Command::Disconnect => {
+            bot_mutex
+                .lock()
+                .unwrap()
+                .state
+                .transition(BotState::Stopped);
+            disconnect(peer_id);
+        }
+    }
+}
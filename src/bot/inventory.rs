@@ -9,10 +9,21 @@ pub struct Inventory {
     pub items: Vec<Item>,
 }
 
+// Most of one item a slot can hold
+pub const MAX_AMOUNT: u8 = 200;
+const EQUIPPED: u8 = 0x1;
+
 #[derive(Debug)]
 pub struct Item {
     pub id: u16,
-    pub amount: u16,
+    pub amount: u8,
+    pub flags: u8,
+}
+
+impl Item {
+    pub fn is_equipped(&self) -> bool {
+        self.flags & EQUIPPED != 0
+    }
 }
 
 impl Inventory {
@@ -24,15 +35,194 @@ impl Inventory {
         }
     }
 
-    pub fn parse(&mut self, data: &[u8]) {
+    pub fn parse(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
         let mut data = Cursor::new(data);
         data.set_position(data.position() + 1);
-        self.size = data.read_u32::<LittleEndian>().unwrap();
-        self.item_count = data.read_u16::<LittleEndian>().unwrap();
-        for _ in 0..self.item_count {
-            let id = data.read_u16::<LittleEndian>().unwrap();
-            let amount = data.read_u16::<LittleEndian>().unwrap();
-            self.items.push(Item { id, amount });
+        let size = data.read_u32::<LittleEndian>()?;
+        let item_count = data.read_u16::<LittleEndian>()?;
+        let mut items = Vec::with_capacity(item_count as usize);
+        for _ in 0..item_count {
+            let id = data.read_u16::<LittleEndian>()?;
+            let amount = data.read_u8()?;
+            let flags = data.read_u8()?;
+            items.push(Item { id, amount, flags });
+        }
+        // Only replaced once the whole packet parsed, a truncated one leaves the old inventory alone
+        self.size = size;
+        self.item_count = item_count;
+        self.items = items;
+        Ok(())
+    }
+    // Picked up or given, returns how many actually fit
+    pub fn add(&mut self, id: u16, amount: u8) -> u8 {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            let added = amount.min(MAX_AMOUNT.saturating_sub(item.amount));
+            item.amount += added;
+            return added;
+        }
+        if self.is_full() {
+            return 0;
+        }
+        let amount = amount.min(MAX_AMOUNT);
+        self.items.push(Item {
+            id,
+            amount,
+            flags: 0,
+        });
+        self.item_count = self.items.len() as u16;
+        amount
+    }
+
+    // Dropped, trashed or used up, the slot is freed once the last one is gone
+    pub fn remove(&mut self, id: u16, amount: u8) {
+        if let Some(index) = self.items.iter().position(|item| item.id == id) {
+            let item = &mut self.items[index];
+            item.amount = item.amount.saturating_sub(amount);
+            if item.amount == 0 {
+                self.items.remove(index);
+                self.item_count = self.items.len() as u16;
+            }
+        }
+    }
+
+    pub fn count(&self, id: u16) -> u8 {
+        self.items
+            .iter()
+            .find(|item| item.id == id)
+            .map_or(0, |item| item.amount)
+    }
+
+    pub fn free_slots(&self) -> u32 {
+        self.size.saturating_sub(self.items.len() as u32)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.free_slots() == 0
+    }
+
+    pub fn is_equipped(&self, id: u16) -> bool {
+        self.items
+            .iter()
+            .any(|item| item.id == id && item.is_equipped())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An inventory packet the way the server sends it: one unknown byte, then the header and items
+    fn packet(size: u32, items: &[(u16, u8, u8)]) -> Vec<u8> {
+        let mut data = vec![0];
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&(items.len() as u16).to_le_bytes());
+        for (id, amount, flags) in items {
+            data.extend_from_slice(&id.to_le_bytes());
+            data.extend_from_slice(&[*amount, *flags]);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_items() {
+        let mut inventory = Inventory::new();
+        inventory
+            .parse(&packet(16, &[(2, 200, 0), (18, 1, EQUIPPED)]))
+            .unwrap();
+        assert_eq!(inventory.size, 16);
+        assert_eq!(inventory.item_count, 2);
+        assert_eq!(inventory.count(2), 200);
+        assert!(inventory.is_equipped(18));
+        assert!(!inventory.is_equipped(2));
+    }
+
+    #[test]
+    fn rejects_every_truncation() {
+        let data = packet(16, &[(2, 200, 0), (18, 1, EQUIPPED)]);
+        for len in 0..data.len() {
+            let mut inventory = Inventory::new();
+            assert!(
+                inventory.parse(&data[..len]).is_err(),
+                "truncated to {}",
+                len
+            );
         }
     }
+
+    #[test]
+    fn truncated_packet_keeps_the_old_inventory() {
+        let mut inventory = Inventory::new();
+        inventory.parse(&packet(16, &[(2, 200, 0)])).unwrap();
+
+        let data = packet(32, &[(4, 10, 0), (6, 20, 0)]);
+        assert!(inventory.parse(&data[..data.len() - 1]).is_err());
+
+        assert_eq!(inventory.size, 16);
+        assert_eq!(inventory.count(2), 200);
+        assert_eq!(inventory.count(4), 0);
+    }
+
+    #[test]
+    fn add_stacks_up_to_the_cap() {
+        let mut inventory = Inventory::new();
+        inventory.parse(&packet(16, &[(2, 150, 0)])).unwrap();
+
+        assert_eq!(inventory.add(2, 100), 50);
+        assert_eq!(inventory.count(2), MAX_AMOUNT);
+        assert_eq!(inventory.add(2, 1), 0);
+        assert_eq!(inventory.items.len(), 1);
+    }
+
+    #[test]
+    fn add_caps_a_new_stack() {
+        let mut inventory = Inventory::new();
+        inventory.parse(&packet(16, &[])).unwrap();
+
+        assert_eq!(inventory.add(4, 255), MAX_AMOUNT);
+        assert_eq!(inventory.count(4), MAX_AMOUNT);
+        assert_eq!(inventory.item_count, 1);
+        assert_eq!(inventory.free_slots(), 15);
+    }
+
+    #[test]
+    fn full_inventory_only_stacks() {
+        let mut inventory = Inventory::new();
+        inventory
+            .parse(&packet(2, &[(2, 10, 0), (4, 10, 0)]))
+            .unwrap();
+        assert!(inventory.is_full());
+
+        assert_eq!(inventory.add(6, 1), 0);
+        assert_eq!(inventory.count(6), 0);
+        assert_eq!(inventory.add(2, 5), 5);
+        assert_eq!(inventory.count(2), 15);
+    }
+
+    #[test]
+    fn removing_the_last_one_frees_the_slot() {
+        let mut inventory = Inventory::new();
+        inventory
+            .parse(&packet(2, &[(2, 10, 0), (4, 10, 0)]))
+            .unwrap();
+
+        inventory.remove(2, 4);
+        assert_eq!(inventory.count(2), 6);
+        assert!(inventory.is_full());
+
+        inventory.remove(2, 6);
+        assert_eq!(inventory.count(2), 0);
+        assert_eq!(inventory.item_count, 1);
+        assert_eq!(inventory.free_slots(), 1);
+        assert_eq!(inventory.add(6, 1), 1);
+    }
+
+    #[test]
+    fn removing_more_than_there_is_empties_the_slot() {
+        let mut inventory = Inventory::new();
+        inventory.parse(&packet(16, &[(2, 10, 0)])).unwrap();
+
+        inventory.remove(2, 50);
+        inventory.remove(4, 1);
+        assert!(inventory.items.is_empty());
+    }
 }
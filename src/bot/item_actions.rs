@@ -0,0 +1,225 @@
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+// How many of the item are left once the server confirmed, or why it didn't happen
+pub type ItemActionResult = Result<u8, String>;
+
+// Actions the server answers with a dialog asking how many
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemAction {
+    Drop,
+    Trash,
+}
+
+impl ItemAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemAction::Drop => "drop",
+            ItemAction::Trash => "trash",
+        }
+    }
+
+    pub fn dialog_name(&self) -> &'static str {
+        match self {
+            ItemAction::Drop => "drop_item",
+            ItemAction::Trash => "trash_item",
+        }
+    }
+}
+
+// Sent, then answered with the amount once its dialog shows up, then confirmed by the inventory update
+#[derive(Debug)]
+pub struct PendingItemAction {
+    pub action: ItemAction,
+    pub item_id: u16,
+    pub amount: u8,
+    answered: bool,
+    sent_at: Instant,
+    reply: Option<Sender<ItemActionResult>>,
+}
+
+impl PendingItemAction {
+    fn finish(&mut self, result: ItemActionResult) {
+        if let Some(reply) = self.reply.take() {
+            // Whoever asked may have stopped waiting
+            let _ = reply.send(result);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ItemActions {
+    pending: Vec<PendingItemAction>,
+}
+
+impl ItemActions {
+    pub fn new() -> ItemActions {
+        ItemActions {
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn start(
+        &mut self,
+        action: ItemAction,
+        item_id: u16,
+        amount: u8,
+        reply: Option<Sender<ItemActionResult>>,
+    ) {
+        self.pending.push(PendingItemAction {
+            action,
+            item_id,
+            amount,
+            answered: false,
+            sent_at: Instant::now(),
+            reply,
+        });
+    }
+
+    // The dialog the server opened for one of ours, matched by its name and the item it embeds.
+    // Returns the action to answer it with.
+    pub fn answer(&mut self, dialog: &str) -> Option<&PendingItemAction> {
+        let item_id = dialog_item_id(dialog)?;
+        let pending = self.pending.iter_mut().find(|pending| {
+            !pending.answered
+                && pending.item_id == item_id
+                && dialog.contains(&format!("end_dialog|{}|", pending.action.dialog_name()))
+        })?;
+        pending.answered = true;
+        Some(pending)
+    }
+
+    // A NetGamePacketModifyItemInventory that removed exactly what an answered action asked for
+    pub fn confirm(&mut self, item_id: u16, removed: u8, left: u8) -> Option<ItemAction> {
+        let index = self.pending.iter().position(|pending| {
+            pending.answered && pending.item_id == item_id && pending.amount == removed
+        })?;
+        let mut pending = self.pending.remove(index);
+        pending.finish(Ok(left));
+        Some(pending.action)
+    }
+
+    // Actions the server never confirmed, whoever asked is told so
+    pub fn expire(&mut self, timeout: Duration) -> Vec<PendingItemAction> {
+        let (mut expired, pending): (Vec<_>, Vec<_>) = self
+            .pending
+            .drain(..)
+            .partition(|pending| pending.sent_at.elapsed() >= timeout);
+        self.pending = pending;
+        for pending in &mut expired {
+            pending.finish(Err(format!(
+                "The server never confirmed the {} of item {}",
+                pending.action.name(),
+                pending.item_id
+            )));
+        }
+        expired
+    }
+
+    // Nothing will be confirmed anymore, e.g. after a disconnect
+    pub fn clear(&mut self, reason: &str) {
+        for mut pending in self.pending.drain(..) {
+            pending.finish(Err(format!(
+                "Gave up on the {} of item {}: {}",
+                pending.action.name(),
+                pending.item_id,
+                reason
+            )));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+// The dialog carries the item as `embed_data|itemID|<id>`
+fn dialog_item_id(dialog: &str) -> Option<u16> {
+    dialog
+        .lines()
+        .find_map(|line| line.strip_prefix("embed_data|itemID|"))
+        .and_then(|value| value.split('|').next())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, TryRecvError};
+
+    fn dialog(name: &str, item_id: u16) -> String {
+        format!(
+            "add_label_with_icon|big|`wDrop Dirt``|left|{0}|\nadd_text_input|count||1|5|\nembed_data|itemID|{0}\nend_dialog|{1}|Cancel|OK|\n",
+            item_id, name
+        )
+    }
+
+    #[test]
+    fn answers_the_dialog_of_the_same_item() {
+        let mut actions = ItemActions::new();
+        actions.start(ItemAction::Drop, 2, 5, None);
+        actions.start(ItemAction::Drop, 4, 1, None);
+
+        let pending = actions.answer(&dialog("drop_item", 4)).unwrap();
+        assert_eq!((pending.item_id, pending.amount), (4, 1));
+        // Already answered
+        assert!(actions.answer(&dialog("drop_item", 4)).is_none());
+    }
+
+    #[test]
+    fn ignores_other_dialogs() {
+        let mut actions = ItemActions::new();
+        actions.start(ItemAction::Trash, 2, 5, None);
+
+        assert!(actions.answer(&dialog("drop_item", 2)).is_none());
+        assert!(actions.answer(&dialog("trash_item", 3)).is_none());
+        assert!(actions.answer("end_dialog|trash_item|Cancel|OK|").is_none());
+        assert!(actions.answer(&dialog("trash_item", 2)).is_some());
+    }
+
+    #[test]
+    fn confirms_only_the_removed_amount_of_the_item() {
+        let (reply, receiver) = mpsc::channel();
+        let mut actions = ItemActions::new();
+        actions.start(ItemAction::Drop, 2, 5, Some(reply));
+
+        // Not answered yet
+        assert_eq!(actions.confirm(2, 5, 10), None);
+        actions.answer(&dialog("drop_item", 2)).unwrap();
+        assert_eq!(actions.confirm(3, 5, 10), None);
+        assert_eq!(actions.confirm(2, 4, 10), None);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        assert_eq!(actions.confirm(2, 5, 10), Some(ItemAction::Drop));
+        assert_eq!(receiver.try_recv(), Ok(Ok(10)));
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn expired_actions_are_failed() {
+        let (reply, receiver) = mpsc::channel();
+        let mut actions = ItemActions::new();
+        actions.start(ItemAction::Trash, 2, 5, Some(reply));
+
+        assert!(actions.expire(Duration::from_secs(60)).is_empty());
+        let expired = actions.expire(Duration::ZERO);
+        assert_eq!(expired.len(), 1);
+        assert!(actions.is_empty());
+        assert!(receiver.try_recv().unwrap().is_err());
+    }
+
+    #[test]
+    fn clearing_fails_every_action() {
+        let (reply, receiver) = mpsc::channel();
+        let mut actions = ItemActions::new();
+        actions.start(ItemAction::Drop, 2, 5, Some(reply.clone()));
+        actions.start(ItemAction::Trash, 4, 1, Some(reply));
+
+        actions.clear("disconnected");
+
+        assert!(actions.is_empty());
+        let err = receiver.try_recv().unwrap().unwrap_err();
+        assert!(err.contains("disconnected"), "{}", err);
+        assert!(receiver.try_recv().unwrap().is_err());
+    }
+}
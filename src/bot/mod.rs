@@ -1,33 +1,59 @@
 mod astar;
-mod inventory;
+pub mod capture;
+pub mod command;
+pub mod inventory;
+pub mod item_actions;
 mod login;
-mod packet_handler;
+mod objects;
+pub mod packet_handler;
+pub mod reactor;
+pub mod replay;
+pub mod supervisor;
 mod variant_handler;
 
 use crate::types::bot_info::{Info, Position, Server, State};
+use crate::types::bot_state::BotState;
 use crate::types::e_login_method::ELoginMethod;
 use crate::types::e_tank_packet_type::ETankPacketType;
 use crate::types::login_info::LoginInfo;
+use crate::types::player::Player;
+use crate::types::proxy::Proxy;
+use crate::types::reconnect_policy::{DisconnectReason, ReconnectPolicy};
 use crate::types::tank_packet_type::TankPacketType;
+use crate::types::text_packet::TextPacket;
+use crate::utils::http::{HttpError, Request, UreqClient};
 use crate::utils::proton::hash_string;
 use crate::utils::random::random_hex;
+use crate::utils::socks5::UdpRelay;
 use crate::{types::e_packet_type::EPacketType, utils::proton::generate_klv};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use astar::AStar;
-use byteorder::{ByteOrder, LittleEndian};
+use capture::{Direction, PacketLog, Recorder};
+use command::Command;
 use enet::*;
 use gtitem_r::structs::ItemDatabase;
 use gtworld_r::World;
 use inventory::Inventory;
-use spdlog::{error, info};
+use item_actions::{ItemAction, ItemActionResult, ItemActions};
+use objects::{DroppedObject, Objects};
+use reactor::Reactor;
+use spdlog::{error, info, warn};
 use std::cell::{Cell, RefCell};
 
 static USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0";
 
+// How long the server gets to hand over a collected object
+const COLLECT_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a drop or trash gets to be confirmed by an inventory update
+const ITEM_ACTION_TIMEOUT: Duration = Duration::from_secs(5);
+
 thread_local! {
     static ENET_HOST: RefCell<Option<Host<()>>> = RefCell::new(None);
 }
@@ -36,11 +62,22 @@ pub struct Bot {
     pub info: Info,
     pub state: State,
     pub position: Position,
+    // Everyone else in the current world, keyed by net_id
+    pub players: HashMap<u32, Player>,
     pub server: Server,
     pub world: World,
+    pub objects: Objects,
     pub inventory: Inventory,
     pub astar: AStar,
     pub peer_id: Option<PeerID>,
+    pub command_queue: VecDeque<Command>,
+    pub item_actions: ItemActions,
+    pub recent_packets: VecDeque<Vec<u8>>,
+    pub proxy_relay: Option<UdpRelay>,
+    pub capture: Option<Arc<Recorder>>,
+    pub packet_log: Arc<PacketLog>,
+    // Every thread started for this bot, see supervisor::spawn_isolated
+    pub threads: Vec<JoinHandle<()>>,
 }
 
 impl Bot {
@@ -49,6 +86,8 @@ impl Bot {
         password: String,
         code: String,
         method: ELoginMethod,
+        reconnect_policy: ReconnectPolicy,
+        proxy: Option<Proxy>,
         item_database: Arc<ItemDatabase>,
     ) -> Bot {
         Bot {
@@ -57,39 +96,78 @@ impl Bot {
                 password,
                 code,
                 method,
+                reconnect_policy,
+                http: Arc::new(UreqClient::new(proxy.as_ref())),
+                proxy,
                 login_info: LoginInfo::new(),
                 ..Default::default()
             },
             state: Default::default(),
             position: Default::default(),
+            players: HashMap::new(),
             server: Default::default(),
             world: World::new(Arc::clone(&item_database)),
+            objects: Objects::new(),
             inventory: Inventory::new(),
             astar: AStar::new(Arc::clone(&item_database)),
             peer_id: None,
+            command_queue: VecDeque::new(),
+            item_actions: ItemActions::new(),
+            recent_packets: VecDeque::new(),
+            proxy_relay: None,
+            capture: None,
+            packet_log: Arc::new(PacketLog::new()),
+            threads: Vec::new(),
         }
     }
+
+    // True once every thread started for the bot has returned
+    pub fn threads_finished(&mut self) -> bool {
+        self.threads.retain(|handle| !handle.is_finished());
+        self.threads.is_empty()
+    }
+
+    // Gets a stopped or crashed bot ready to log in again
+    pub fn reset(&mut self) {
+        self.command_queue.clear();
+        self.item_actions.clear("the bot was reset");
+        self.players.clear();
+        self.objects.clear();
+        self.peer_id = None;
+        self.proxy_relay = None;
+        self.state.transition(BotState::Idle);
+    }
 }
 
-pub fn login(bot_mutex: Arc<Mutex<Bot>>) {
-    to_http(&bot_mutex);
+pub fn login(bot_mutex: Arc<Mutex<Bot>>, reactor: Reactor) {
+    if let Err(err) = authenticate(&bot_mutex) {
+        error!("{}", err);
+        let mut bot = bot_mutex.lock().unwrap();
+        bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+        bot.state.transition(BotState::Stopped);
+        return;
+    }
+    connect(&bot_mutex, &reactor);
+}
+
+// Everything before the first connect: server data, OAuth links, the token and the login info
+pub fn authenticate(bot_mutex: &Arc<Mutex<Bot>>) -> Result<(), String> {
+    to_http(bot_mutex).map_err(|err| format!("Failed to get server data: {}", err))?;
     if bot_mutex.lock().unwrap().info.method != ELoginMethod::UBISOFT {
-        match get_oauth_links(&bot_mutex) {
-            Ok(links) => {
-                let mut bot = bot_mutex.lock().unwrap();
-                bot.info.oauth_links = links;
-                info!("Successfully got OAuth links for: apple, google and legacy");
-            }
-            Err(err) => {
-                info!("Failed to get OAuth links: {}", err);
-                return;
-            }
-        }
+        let links = get_oauth_links(bot_mutex)
+            .map_err(|err| format!("Failed to get OAuth links: {}", err))?;
+        let mut bot = bot_mutex.lock().unwrap();
+        bot.info.oauth_links = links;
+        info!("Successfully got OAuth links for: apple, google and legacy");
     }
-    get_token(&bot_mutex);
+    get_token(bot_mutex).map_err(|err| format!("Failed to get token: {}", err))?;
     let mut bot = bot_mutex.lock().unwrap();
-    bot.state.is_running = true;
-    bot.info.login_info.meta = bot.info.parsed_server_data["meta"].clone();
+    bot.info.login_info.meta = bot
+        .info
+        .parsed_server_data
+        .get("meta")
+        .cloned()
+        .ok_or("Server data has no meta")?;
 
     bot.info.login_info.klv = generate_klv(
         &bot.info.login_info.protocol,
@@ -100,132 +178,123 @@ pub fn login(bot_mutex: Arc<Mutex<Bot>>) {
         hash_string(format!("{}RT", bot.info.login_info.mac).as_str()).to_string();
     bot.info.login_info.hash2 =
         hash_string(format!("{}RT", random_hex(16, true)).as_str()).to_string();
-    drop(bot);
-    start_event_loop(&bot_mutex);
+    Ok(())
 }
 
-pub fn start_event_loop(bot_mutex: &Arc<Mutex<Bot>>) {
-    let enet = Enet::new().expect("Failed to initialize ENet");
-
+// Does the blocking part of (re)connecting on the calling thread and hands the bot
+// to its reactor once there is a server to connect to
+pub fn connect(bot_mutex: &Arc<Mutex<Bot>>, reactor: &Reactor) {
     loop {
-        let (is_running, is_redirect, is_ingame, server_ip, server_port, parsed_server_data) = {
+        let (state, previous_state) = {
             let bot = bot_mutex.lock().unwrap();
-            (
-                bot.state.is_running,
-                bot.state.is_redirect,
-                bot.state.is_ingame,
-                bot.server.ip.clone(),
-                bot.server.port.clone(),
-                bot.info.parsed_server_data.clone(),
-            )
+            (bot.state.current(), bot.state.previous())
         };
 
-        if !is_running {
-            break;
+        if !state.is_running() {
+            return;
         }
 
-        ENET_HOST.set(Some(
-            Enet::create_host::<()>(
-                &enet,
-                None,
-                1,
-                ChannelLimit::Limited(1),
-                BandwidthLimit::Unlimited,
-                BandwidthLimit::Unlimited,
-                true,
-                false,
-            )
-            .expect("Failed to create ENet host"),
-        ));
-
-        if is_redirect {
-            info!("Redirecting to {}:{}...", &server_ip, &server_port);
-            connect_to_server(&server_ip, &server_port);
-        } else {
-            if is_ingame {
-                get_token(&bot_mutex);
+        if state == BotState::Reconnecting {
+            let mut bot = bot_mutex.lock().unwrap();
+            let reason = bot
+                .state
+                .disconnect_reason
+                .unwrap_or(DisconnectReason::NetworkError);
+            match bot
+                .info
+                .reconnect_policy
+                .delay(reason, bot.state.reconnect_attempts)
+            {
+                Some(delay) => {
+                    bot.state.reconnect_attempts += 1;
+                    info!(
+                        "Reconnecting in {}ms ({}, attempt {})",
+                        delay.as_millis(),
+                        reason,
+                        bot.state.reconnect_attempts
+                    );
+                    drop(bot);
+                    if !wait_while(bot_mutex, BotState::Reconnecting, delay) {
+                        continue;
+                    }
+                }
+                None => {
+                    warn!(
+                        "Giving up after {} reconnect attempts ({})",
+                        bot.state.reconnect_attempts, reason
+                    );
+                    bot.state.transition(BotState::Stopped);
+                    return;
+                }
             }
-            to_http(&bot_mutex);
-            info!(
-                "Connecting to {}:{}",
-                parsed_server_data["server"], parsed_server_data["port"]
-            );
-            connect_to_server(&parsed_server_data["server"], &parsed_server_data["port"]);
+            if matches!(previous_state, BotState::InGame | BotState::InWorld) {
+                if let Err(err) = get_token(bot_mutex) {
+                    error!("Failed to get token: {}", err);
+                    let mut bot = bot_mutex.lock().unwrap();
+                    bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+                    bot.state.transition(BotState::Reconnecting);
+                    continue;
+                }
+            }
+        }
+
+        if let Err(err) = to_http(bot_mutex) {
+            error!("Failed to get server data: {}", err);
+            let mut bot = bot_mutex.lock().unwrap();
+            bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+            bot.state.transition(BotState::Reconnecting);
+            continue;
         }
-        process_events(&bot_mutex);
+        bot_mutex
+            .lock()
+            .unwrap()
+            .state
+            .transition(BotState::Connecting);
+        reactor.submit(Arc::clone(bot_mutex));
+        return;
     }
 }
 
-fn get_event(bot_mutex: &Arc<Mutex<Bot>>) -> Option<EventKind> {
-    ENET_HOST.with(|enet_host| {
-        let mut enet_host = enet_host.borrow_mut();
-        let enet_host = enet_host.as_mut().unwrap();
-        let e = enet_host
-            .service(std::time::Duration::from_secs(1))
-            .expect("Service failed");
-
-        match e {
-            Some(event) => {
-                bot_mutex.lock().unwrap().peer_id = Some(event.peer_id());
-                Some(event.take_kind())
-            }
-            None => None,
+// Sleeps in small steps so a stopped bot doesn't have to wait out the whole delay,
+// returns false when the bot left the given state in the meantime
+fn wait_while(bot_mutex: &Arc<Mutex<Bot>>, state: BotState, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        if bot_mutex.lock().unwrap().state.current() != state {
+            return false;
         }
-    })
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100).min(deadline - now));
+    }
 }
 
-fn process_events(bot_mutex: &Arc<Mutex<Bot>>) {
+fn process_commands(bot_mutex: &Arc<Mutex<Bot>>) {
     loop {
-        let event = match get_event(bot_mutex) {
-            Some(event) => event,
-            None => {
-                continue;
-            }
-        };
-
-        match event {
-            EventKind::Connect => {
-                if let Ok(mut bot) = bot_mutex.lock() {
-                    bot.info.status = "Connected".to_string();
-                    info!("Connected to the server");
-                }
-            }
-            EventKind::Disconnect { .. } => {
-                if let Ok(mut bot) = bot_mutex.lock() {
-                    bot.info.status = "Disconnected".to_string();
-                    info!("Disconnected from the server");
-                }
+        // The lock must be released before handling, commands lock the bot themselves
+        let command = {
+            let mut bot = bot_mutex.lock().unwrap();
+            if bot.state.paused && bot.state.current().is_running() {
                 break;
             }
-            EventKind::Receive { packet, .. } => {
-                set_ping(bot_mutex);
-                let data = packet.data();
-                if data.len() < 4 {
-                    return;
-                }
-                let packet_id = LittleEndian::read_u32(&data[0..4]);
-                let packet_type = EPacketType::from(packet_id);
-                packet_handler::handle(bot_mutex, packet_type, &data[4..]);
-            }
+            bot.command_queue.pop_front()
+        };
+        let Some(command) = command else {
+            break;
+        };
+        // One step per pass, a long path would otherwise hold up every other bot on the reactor
+        let is_step = matches!(command, Command::Walk { .. });
+        command::handle(bot_mutex, command);
+        if is_step {
+            break;
         }
     }
 }
 
-fn connect_to_server(ip: &str, port: &str) {
-    ENET_HOST.with_borrow_mut(|enet_host| {
-        let enet_host = enet_host.as_mut().unwrap();
-        enet_host
-            .connect(
-                &Address::new(ip.parse().unwrap(), port.parse().unwrap()),
-                2,
-                0,
-            )
-            .expect("Failed to connect to the server");
-    });
-}
-
-pub fn get_token(bot_mutex: &Arc<Mutex<Bot>>) {
-    let (username, password, code, method, oauth_links) = {
+pub fn get_token(bot_mutex: &Arc<Mutex<Bot>>) -> Result<(), String> {
+    let (username, password, code, method, oauth_links, proxy, http, endpoints) = {
         let bot = bot_mutex.lock().unwrap();
         (
             bot.info.username.clone(),
@@ -233,84 +302,109 @@ pub fn get_token(bot_mutex: &Arc<Mutex<Bot>>) {
             bot.info.code.clone(),
             bot.info.method.clone(),
             bot.info.oauth_links.clone(),
+            bot.info.proxy.clone(),
+            Arc::clone(&bot.info.http),
+            bot.info.endpoints.clone(),
         )
     };
 
     {
         let mut bot = bot_mutex.lock().unwrap();
-        bot.info.status = "Getting token".to_string();
+        bot.state.transition(BotState::Authenticating);
     }
 
     info!("Getting token for {}", username);
 
-    let token_result = match method {
-        ELoginMethod::UBISOFT => login::get_ubisoft_token(&username, &password, &code),
-        ELoginMethod::APPLE => match login::get_apple_token(oauth_links[0].as_str()) {
-            Ok(res) => Ok(res),
-            Err(..) => {
-                return;
-            }
-        },
-        ELoginMethod::GOOGLE => {
-            match login::get_google_token(&username, &password, oauth_links[1].as_str()) {
-                Ok(res) => Ok(res),
-                Err(err) => {
-                    if err.to_string().contains("too many people") {
-                        error!("Too many people trying to login");
-                        let mut bot = bot_mutex.lock().unwrap();
-                        bot.info.status = "Too many people trying to login".to_string();
-                    }
-                    return;
-                }
+    // The dashboard lists the apple, google and legacy links in that order
+    let oauth_link = |index: usize| {
+        oauth_links
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("the dashboard has no {:?} login link", method))
+    };
+    let token = match method {
+        ELoginMethod::UBISOFT => {
+            login::get_ubisoft_token(http.as_ref(), &endpoints, &username, &password, &code)
+                .map_err(|err| err.to_string())?
+        }
+        ELoginMethod::APPLE => {
+            if proxy.is_some() {
+                warn!("Apple login opens the system browser, it won't use the proxy");
             }
+            login::get_apple_token(oauth_link(0)?).map_err(|err| err.to_string())?
         }
-
-        ELoginMethod::LEGACY => {
-            login::get_legacy_token(oauth_links[2].as_str(), &username, &password)
+        ELoginMethod::GOOGLE => {
+            login::get_google_token(&username, &password, oauth_link(1)?, proxy.as_ref())
+                .map_err(|err| err.to_string())?
         }
+        ELoginMethod::LEGACY => login::get_legacy_token(
+            http.as_ref(),
+            &endpoints,
+            oauth_link(2)?,
+            &username,
+            &password,
+        )
+        .map_err(|err| err.to_string())?,
     };
 
-    if let Ok(token) = token_result {
-        let mut bot = bot_mutex.lock().unwrap();
-        bot.info.token = token;
-        info!("Received the token: {}", bot.info.token);
-    }
+    let mut bot = bot_mutex.lock().unwrap();
+    bot.info.token = token;
+    info!("Received the token: {}", bot.info.token);
+    Ok(())
 }
 
-pub fn to_http(bot_mutex: &Arc<Mutex<Bot>>) {
-    bot_mutex.lock().unwrap().info.status = "Connecting to HTTP server".to_string();
-    let req = ureq::post("https://www.growtopia1.com/growtopia/server_data.php").set(
+pub fn to_http(bot_mutex: &Arc<Mutex<Bot>>) -> Result<(), HttpError> {
+    let (http, server_data_url) = {
+        let mut bot = bot_mutex.lock().unwrap();
+        bot.state.transition(BotState::FetchingServerData);
+        (
+            Arc::clone(&bot.info.http),
+            bot.info.endpoints.server_data.clone(),
+        )
+    };
+    let body = http.send(Request::post(server_data_url).header(
         "User-Agent",
         "UbiServices_SDK_2022.Release.9_PC64_ansi_static",
-    );
-
-    let res = req.send_string("").unwrap();
-
-    let body = res.into_string().unwrap();
+    ))?;
     parse_server_data(&bot_mutex, body);
+    Ok(())
 }
 
-pub fn find_path(bot_mutex: &Arc<Mutex<Bot>>, peer_id: PeerID, x: u32, y: u32) {
-    let bot = bot_mutex.lock().unwrap();
-    let paths = match bot.astar.find_path(
+// The steps go to the front of the queue and are walked one per reactor pass
+pub fn find_path(bot_mutex: &Arc<Mutex<Bot>>, x: u32, y: u32) {
+    let mut bot = bot_mutex.lock().unwrap();
+    if let Some(steps) = path_to(&bot, x, y) {
+        queue_next(&mut bot, steps);
+    }
+}
+
+fn path_to(bot: &Bot, x: u32, y: u32) -> Option<Vec<Command>> {
+    let path = bot.astar.find_path(
         (bot.position.x as u32) / 32,
         (bot.position.y as u32) / 32,
         x,
         y,
-    ) {
-        Some(path) => path,
-        None => return,
-    };
+    )?;
+    Some(
+        path.iter()
+            .map(|node| Command::Walk {
+                x: node.x as f32,
+                y: node.y as f32,
+                ap: true,
+            })
+            .collect(),
+    )
+}
 
-    for i in 0..paths.len() {
-        let node = &paths[i];
-        walk(&bot_mutex, peer_id, node.x as f32, node.y as f32, true);
+// Runs the commands before anything queued earlier, in the order given
+fn queue_next(bot: &mut Bot, commands: Vec<Command>) {
+    for command in commands.into_iter().rev() {
+        bot.command_queue.push_front(command);
     }
 }
 
 pub fn parse_server_data(bot_mutex: &Arc<Mutex<Bot>>, data: String) {
     let mut bot = bot_mutex.lock().unwrap();
-    bot.info.status = "Parsing server data".to_string();
     bot.info.parsed_server_data = data
         .lines()
         .filter_map(|line| {
@@ -346,39 +440,17 @@ pub fn walk(bot_mutex: &Arc<Mutex<Bot>>, peer_id: PeerID, x: f32, y: f32, ap: bo
     pkt.int_x = -1;
     pkt.int_y = -1;
 
-    let mut packet_data = Vec::new();
-    packet_data.extend_from_slice(&(EPacketType::NetMessageGamePacket as u32).to_le_bytes());
-    packet_data.extend_from_slice(&(pkt.packet_type as u8).to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk1.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk2.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk3.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.net_id.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk4.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.flags.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk6.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.value.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_x.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_y.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_x2.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_y2.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk12.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.int_x.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.int_y.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.extended_data_length.to_le_bytes());
-
-    let pkt = Packet::new(packet_data, PacketMode::ReliableSequenced).unwrap();
-    ENET_HOST.with_borrow_mut(|enet_host| {
-        let enet_host = enet_host.as_mut().unwrap();
-        let peer = enet_host.peer_mut(peer_id).unwrap();
-        peer.send_packet(pkt, 0).unwrap();
-    });
+    send_tank_packet(peer_id, &pkt);
 }
 
 pub fn talk(peer_id: PeerID, message: &str) {
     send_packet(
         peer_id,
         EPacketType::NetMessageGenericText,
-        format!("action|input\n|text|{}\n", message),
+        // The chat line is sent with an empty key, e.g. "|text|hello"
+        TextPacket::action("input")
+            .with("", format!("text|{}", message))
+            .serialize(),
     );
 }
 
@@ -399,37 +471,12 @@ pub fn place(
     pkt.int_y = ((bot.position.y / 32.0).floor() as i32) + offset_y;
     pkt.value = block_id;
 
-    let mut packet_data = Vec::new();
-    packet_data.extend_from_slice(&(EPacketType::NetMessageGamePacket as u32).to_le_bytes());
-    packet_data.extend_from_slice(&(pkt.packet_type as u8).to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk1.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk2.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk3.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.net_id.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk4.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.flags.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk6.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.value.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_x.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_y.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_x2.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.vector_y2.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.unk12.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.int_x.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.int_y.to_le_bytes());
-    packet_data.extend_from_slice(&pkt.extended_data_length.to_le_bytes());
-
     if pkt.int_x <= (bot.position.x / 32.0).floor() as i32 + 4
         && pkt.int_x >= (bot.position.x / 32.0).floor() as i32 - 4
         && pkt.int_y <= (bot.position.y / 32.0).floor() as i32 + 4
         && pkt.int_y >= (bot.position.y / 32.0).floor() as i32 - 4
     {
-        let pkt = Packet::new(packet_data, PacketMode::ReliableSequenced).unwrap();
-        ENET_HOST.with_borrow_mut(|enet_host| {
-            let enet_host = enet_host.as_mut().unwrap();
-            let peer = enet_host.peer_mut(peer_id).unwrap();
-            peer.send_packet(pkt, 0).unwrap();
-        });
+        send_tank_packet(peer_id, &pkt);
     }
 }
 
@@ -437,12 +484,152 @@ pub fn punch(bot_mutex: &Arc<Mutex<Bot>>, peer_id: PeerID, offset_x: i32, offset
     place(&bot_mutex, peer_id, offset_x, offset_y, 18)
 }
 
+// Walks into pickup range if needed, the pickup is confirmed when the server hands the object over
+pub fn collect(bot_mutex: &Arc<Mutex<Bot>>, peer_id: PeerID, uid: u32) {
+    let mut bot = bot_mutex.lock().unwrap();
+    let object = match bot.objects.get(uid) {
+        Some(object) => object.clone(),
+        None => {
+            info!("Object {} is no longer on the ground", uid);
+            return;
+        }
+    };
+    if objects::distance(&bot.position, &object.position) <= bot.state.collect_range * 32.0 {
+        pick_up(&mut bot, peer_id, &object);
+        return;
+    }
+
+    let target = (
+        (object.position.x / 32.0) as u32,
+        (object.position.y / 32.0) as u32,
+    );
+    match path_to(&bot, target.0, target.1) {
+        Some(mut steps) => {
+            steps.push(Command::PickUp(uid));
+            queue_next(&mut bot, steps);
+        }
+        None => warn!("No path to object {}", uid),
+    }
+}
+
+// Once the walk queued by collect is done
+pub fn pick_up_in_range(bot_mutex: &Arc<Mutex<Bot>>, peer_id: PeerID, uid: u32) {
+    let mut bot = bot_mutex.lock().unwrap();
+    let object = match bot.objects.get(uid) {
+        Some(object) => object.clone(),
+        None => {
+            info!("Object {} is no longer on the ground", uid);
+            return;
+        }
+    };
+    if objects::distance(&bot.position, &object.position) > bot.state.collect_range * 32.0 {
+        warn!("Couldn't get within range of object {}", uid);
+        return;
+    }
+    pick_up(&mut bot, peer_id, &object);
+}
+
+fn pick_up(bot: &mut Bot, peer_id: PeerID, object: &DroppedObject) {
+    let inventory_count = bot.inventory.count(object.item_id);
+    bot.objects.expect_pickup(object, inventory_count);
+
+    let mut pkt = TankPacketType::new();
+    pkt.packet_type = ETankPacketType::NetGamePacketItemActivateObjectRequest;
+    pkt.vector_x = object.position.x;
+    pkt.vector_y = object.position.y;
+    pkt.value = object.uid;
+    send_tank_packet(peer_id, &pkt);
+}
+
+// Queued as one collect per object, so the walks between them are spread over reactor passes
+pub fn collect_all(bot_mutex: &Arc<Mutex<Bot>>, radius: f32) {
+    let mut bot = bot_mutex.lock().unwrap();
+    let commands = bot
+        .objects
+        .within(&bot.position, radius)
+        .iter()
+        .map(|object| Command::Collect(object.uid))
+        .collect::<Vec<_>>();
+    info!("Collecting {} objects", commands.len());
+    queue_next(&mut bot, commands);
+}
+
+// Called by the reactor on every pass, whatever the server never answered is given up on
+pub fn expire_pending(bot: &mut Bot) {
+    for pending in bot.objects.expire_pending(COLLECT_TIMEOUT) {
+        warn!(
+            "Picking up object {} (item {}) was never confirmed",
+            pending.uid, pending.item_id
+        );
+    }
+    for pending in bot.item_actions.expire(ITEM_ACTION_TIMEOUT) {
+        warn!(
+            "The {} of {}x item {} was never confirmed",
+            pending.action.name(),
+            pending.amount,
+            pending.item_id
+        );
+    }
+}
+
+// The amount is sent once the server asks for it, see variant_handler::on_dialog_request.
+// The reply gets the result once the inventory update confirmed it, or why it failed.
+pub fn item_action(
+    bot_mutex: &Arc<Mutex<Bot>>,
+    peer_id: PeerID,
+    action: ItemAction,
+    item_id: u16,
+    amount: u8,
+    reply: Option<Sender<ItemActionResult>>,
+) {
+    {
+        let mut bot = bot_mutex.lock().unwrap();
+        let count = bot.inventory.count(item_id);
+        let refused = if amount == 0 || count < amount {
+            Some(format!(
+                "Can't {} {}x item {}, the inventory has {}",
+                action.name(),
+                amount,
+                item_id,
+                count
+            ))
+        } else if bot.inventory.is_equipped(item_id) {
+            // The server refuses to drop or trash worn clothes
+            Some(format!(
+                "Can't {} item {}, it's equipped",
+                action.name(),
+                item_id
+            ))
+        } else {
+            None
+        };
+        if let Some(err) = refused {
+            warn!("{}", err);
+            if let Some(reply) = reply {
+                let _ = reply.send(Err(err));
+            }
+            return;
+        }
+        bot.item_actions.start(action, item_id, amount, reply);
+    }
+    send_packet(
+        peer_id,
+        EPacketType::NetMessageGenericText,
+        TextPacket::action(action.name())
+            .with("", format!("itemID|{}", item_id))
+            .serialize(),
+    );
+}
+
 pub fn warp(peer_id: PeerID, world: &str) {
     info!("Warping to world: {}", world);
     send_packet(
         peer_id,
         EPacketType::NetMessageGameMessage,
-        format!("action|join_request\nname|{}\ninvitedWorld|0\n", world),
+        TextPacket::action("join_request")
+            .with("name", world)
+            .with("invitedWorld", 0)
+            .serialize(),
     );
 }
 
@@ -453,16 +640,36 @@ pub fn send_packet(peer_id: PeerID, packet_type: EPacketType, message: String) {
         let mut packet_data = Vec::new();
         packet_data.extend_from_slice(&(packet_type as u32).to_le_bytes());
         packet_data.extend_from_slice(&message.as_bytes());
-        let pkt = Packet::new(packet_data, PacketMode::ReliableSequenced).unwrap();
-        ENET_HOST.with(|enet_host| {
-            let mut enet_host = enet_host.borrow_mut();
-            if let Some(enet_host) = enet_host.as_mut() {
-                if let Some(peer) = enet_host.peer_mut(peer_id) {
-                    peer.send_packet(pkt, 0).unwrap();
+        send(peer_id, packet_data);
+    }
+}
+
+pub fn send_tank_packet(peer_id: PeerID, pkt: &TankPacketType) {
+    let mut packet_data = Vec::new();
+    packet_data.extend_from_slice(&(EPacketType::NetMessageGamePacket as u32).to_le_bytes());
+    packet_data.extend_from_slice(&pkt.serialize());
+    send(peer_id, packet_data);
+}
+
+fn send(peer_id: PeerID, packet_data: Vec<u8>) {
+    capture::record(peer_id, Direction::Outbound, &packet_data);
+    let pkt = match Packet::new(packet_data, PacketMode::ReliableSequenced) {
+        Ok(pkt) => pkt,
+        Err(err) => {
+            warn!("Failed to create a packet: {}", err);
+            return;
+        }
+    };
+    ENET_HOST.with(|enet_host| {
+        let mut enet_host = enet_host.borrow_mut();
+        if let Some(enet_host) = enet_host.as_mut() {
+            if let Some(peer) = enet_host.peer_mut(peer_id) {
+                if let Err(err) = peer.send_packet(pkt, 0) {
+                    warn!("Failed to send a packet: {}", err);
                 }
             }
-        });
-    }
+        }
+    });
 }
 
 pub fn disconnect(peer_id: PeerID) {
@@ -476,17 +683,37 @@ pub fn disconnect(peer_id: PeerID) {
     });
 }
 
-pub fn get_oauth_links(bot_mutex: &Arc<Mutex<Bot>>) -> Result<Vec<String>, ureq::Error> {
-    let mut bot = bot_mutex.lock().unwrap();
-    bot.info.status = "Getting OAuth links".to_string();
-    let body = ureq::post("https://login.growtopiagame.com/player/login/dashboard")
-            .set("User-Agent", USER_AGENT)
-            .send_string(format!("tankIDName|\ntankIDPass|\nrequestedName|BoardSickle\nf|1\nprotocol|209\ngame_version|4.62\nfz|41745432\nlmode|0\ncbits|1040\nplayer_age|20\nGDPR|3\ncategory|_-5100\ntotalPlaytime|0\nklv|b351d8dacd7a776848b31c74d3d550ec61dbb9b96c3ac67aea85034a84401a87\nhash2|841545814\nmeta|{}\nfhash|-716928004\nrid|01F9EBD204B52C940285667E15C00D62\nplatformID|0,1,1\ndeviceVersion|0\ncountry|us\nhash|-1829975549\nmac|b4:8c:9d:90:79:cf\nwk|66A6ABCD9753A066E39975DED77852A8\nzf|617169524\n", bot.info.parsed_server_data["meta"]).as_str())?
-            .into_string()?;
-    drop(bot);
-    let pattern = regex::Regex::new("https:\\/\\/login\\.growtopiagame\\.com\\/(apple|google|player\\/growid)\\/(login|redirect)\\?token=[^\"]+");
+pub fn get_oauth_links(bot_mutex: &Arc<Mutex<Bot>>) -> Result<Vec<String>, HttpError> {
+    // The request blocks, the bot is unlocked for it like in to_http
+    let (http, login_url, meta) = {
+        let mut bot = bot_mutex.lock().unwrap();
+        bot.state.transition(BotState::Authenticating);
+        (
+            Arc::clone(&bot.info.http),
+            bot.info.endpoints.login.clone(),
+            bot.info.parsed_server_data.get("meta").cloned(),
+        )
+    };
+    let meta =
+        meta.ok_or_else(|| HttpError::InvalidResponse("server data has no meta".to_string()))?;
+    let form = format!(
+        "tankIDName|\ntankIDPass|\nrequestedName|BoardSickle\nf|1\nprotocol|209\ngame_version|4.62\nfz|41745432\nlmode|0\ncbits|1040\nplayer_age|20\nGDPR|3\ncategory|_-5100\ntotalPlaytime|0\nklv|b351d8dacd7a776848b31c74d3d550ec61dbb9b96c3ac67aea85034a84401a87\nhash2|841545814\nmeta|{}\nfhash|-716928004\nrid|01F9EBD204B52C940285667E15C00D62\nplatformID|0,1,1\ndeviceVersion|0\ncountry|us\nhash|-1829975549\nmac|b4:8c:9d:90:79:cf\nwk|66A6ABCD9753A066E39975DED77852A8\nzf|617169524\n",
+        meta
+    );
+    let body = http.send(
+        Request::post(format!("{}/player/login/dashboard", login_url))
+            .header("User-Agent", USER_AGENT)
+            .text(form),
+    )?;
+
+    let pattern = regex::Regex::new(&format!(
+        "{}\\/(apple|google|player\\/growid)\\/(login|redirect)\\?token=[^\"]+",
+        regex::escape(&login_url)
+    ))
+    .map_err(|err| {
+        HttpError::InvalidResponse(format!("no login link pattern for {}: {}", login_url, err))
+    })?;
     let links = pattern
-        .unwrap()
         .find_iter(&body)
         .map(|m| m.as_str().to_owned())
         .collect::<Vec<String>>();
@@ -510,3 +737,250 @@ pub fn set_ping(bot_mutex: &Arc<Mutex<Bot>>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::variant::{Variant, VariantList};
+    use inventory::Item;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::sync::OnceLock;
+    use std::thread;
+
+    const SIZE: u32 = 5;
+
+    fn test_bot() -> Arc<Mutex<Bot>> {
+        let mut bot = Bot::new(
+            "test".to_string(),
+            String::new(),
+            String::new(),
+            ELoginMethod::LEGACY,
+            ReconnectPolicy::default(),
+            None,
+            Arc::new(ItemDatabase::new()),
+        );
+        bot.astar.width = SIZE;
+        bot.astar.height = SIZE;
+        bot.astar.grid = (0..SIZE * SIZE)
+            .map(|i| {
+                let mut node = astar::Node::new();
+                node.x = i % SIZE;
+                node.y = i / SIZE;
+                node
+            })
+            .collect();
+        Arc::new(Mutex::new(bot))
+    }
+
+    // Only a host hands out peer ids, nothing is sent since the test threads have no ENET_HOST
+    // ENet only initializes once per process, so every test gets the same one
+    fn test_peer() -> PeerID {
+        static PEER: OnceLock<PeerID> = OnceLock::new();
+        *PEER.get_or_init(|| {
+            let enet = Enet::new().unwrap();
+            let mut host = enet
+                .create_host::<()>(
+                    None,
+                    1,
+                    ChannelLimit::Limited(1),
+                    BandwidthLimit::Unlimited,
+                    BandwidthLimit::Unlimited,
+                    true,
+                    false,
+                )
+                .unwrap();
+            host.connect(&Address::new(std::net::Ipv4Addr::LOCALHOST, 17091), 2, 0)
+                .unwrap()
+        })
+    }
+
+    fn game_packet(pkt: &TankPacketType) -> Vec<u8> {
+        let mut data = (EPacketType::NetMessageGamePacket as u32)
+            .to_le_bytes()
+            .to_vec();
+        data.extend_from_slice(&pkt.serialize());
+        data
+    }
+
+    // Runs f while another thread keeps grabbing the bot lock, a nested lock never finishes
+    fn finishes_under_contention(
+        bot_mutex: &Arc<Mutex<Bot>>,
+        f: impl FnOnce(Arc<Mutex<Bot>>) + Send + 'static,
+    ) -> bool {
+        let stop = Arc::new(AtomicBool::new(false));
+        let holder = {
+            let bot_mutex = Arc::clone(bot_mutex);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let bot = bot_mutex.lock().unwrap();
+                    thread::sleep(Duration::from_millis(20));
+                    drop(bot);
+                    thread::yield_now();
+                }
+            })
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let bot_mutex = Arc::clone(bot_mutex);
+        thread::spawn(move || {
+            f(bot_mutex);
+            let _ = sender.send(());
+        });
+        let finished = receiver.recv_timeout(Duration::from_secs(5)).is_ok();
+
+        stop.store(true, Ordering::Relaxed);
+        holder.join().unwrap();
+        finished
+    }
+
+    #[test]
+    fn find_path_does_not_nest_the_bot_lock() {
+        let bot_mutex = test_bot();
+        let peer_id = test_peer();
+
+        bot_mutex.lock().unwrap().peer_id = Some(peer_id);
+
+        let finished = finishes_under_contention(&bot_mutex, move |bot_mutex| {
+            find_path(&bot_mutex, SIZE - 1, SIZE - 1);
+            while !bot_mutex.lock().unwrap().command_queue.is_empty() {
+                process_commands(&bot_mutex);
+            }
+        });
+
+        assert!(finished, "find_path deadlocked on the bot lock");
+        let bot = bot_mutex.lock().unwrap();
+        assert_eq!(bot.position.x, ((SIZE - 1) * 32) as f32);
+        assert_eq!(bot.position.y, ((SIZE - 1) * 32) as f32);
+    }
+
+    #[test]
+    fn collect_walks_one_step_per_pass_then_picks_up() {
+        let bot_mutex = test_bot();
+        {
+            let mut bot = bot_mutex.lock().unwrap();
+            bot.peer_id = Some(test_peer());
+            bot.state.collect_range = 1.0;
+            bot.inventory.size = 16;
+            bot.inventory.add(2, 3);
+            bot.objects.items.push(objects::DroppedObject {
+                uid: 1,
+                item_id: 2,
+                count: 5,
+                position: Position {
+                    x: ((SIZE - 1) * 32) as f32,
+                    y: ((SIZE - 1) * 32) as f32,
+                },
+            });
+            bot.command_queue.push_back(Command::Collect(1));
+            bot.command_queue
+                .push_back(Command::Talk("after".to_string()));
+        }
+
+        process_commands(&bot_mutex);
+        let steps = {
+            let bot = bot_mutex.lock().unwrap();
+            assert!(matches!(bot.command_queue.back(), Some(Command::Talk(_))));
+            assert!(matches!(
+                bot.command_queue.get(bot.command_queue.len() - 2),
+                Some(Command::PickUp(1))
+            ));
+            // The first step was already walked
+            bot.command_queue.len() - 2
+        };
+        assert!(steps > 0);
+        for walked in 1..=steps {
+            process_commands(&bot_mutex);
+            assert_eq!(
+                bot_mutex.lock().unwrap().command_queue.len(),
+                steps + 2 - walked
+            );
+        }
+        // Picking up and the rest of the queue go in one pass
+        process_commands(&bot_mutex);
+
+        let mut bot = bot_mutex.lock().unwrap();
+        assert!(bot.command_queue.is_empty());
+        let pending = bot.objects.expire_pending(Duration::ZERO);
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].uid, pending[0].inventory_count), (1, 3));
+    }
+
+    #[test]
+    fn astar_update_does_not_nest_the_bot_lock() {
+        let bot_mutex = test_bot();
+
+        let finished = finishes_under_contention(&bot_mutex, |bot_mutex| {
+            let mut bot = bot_mutex.lock().unwrap();
+            let bot = &mut *bot;
+            bot.astar.update(&bot.world);
+        });
+
+        assert!(finished, "AStar::update deadlocked on the bot lock");
+        let bot = bot_mutex.lock().unwrap();
+        assert_eq!(bot.astar.width, bot.world.width);
+        assert_eq!(bot.astar.grid.len(), bot.world.tiles.len());
+    }
+
+    #[test]
+    fn drop_is_confirmed_by_the_inventory_update() {
+        let bot_mutex = test_bot();
+        let peer_id = test_peer();
+        {
+            let mut bot = bot_mutex.lock().unwrap();
+            bot.peer_id = Some(peer_id);
+            bot.inventory.size = 16;
+            bot.inventory.add(2, 10);
+        }
+        let (reply, receiver) = mpsc::channel();
+        item_action(&bot_mutex, peer_id, ItemAction::Drop, 2, 4, Some(reply));
+
+        let mut dialog = TankPacketType::new();
+        dialog.packet_type = ETankPacketType::NetGamePacketCallFunction;
+        dialog.extended_data = VariantList::from(vec![
+            Variant::String("OnDialogRequest".to_string()),
+            Variant::String("embed_data|itemID|2\nend_dialog|drop_item|Cancel|OK|\n".to_string()),
+        ])
+        .serialize()
+        .unwrap();
+        packet_handler::handle(&bot_mutex, &game_packet(&dialog)).unwrap();
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+
+        let mut update = TankPacketType::new();
+        update.packet_type = ETankPacketType::NetGamePacketModifyItemInventory;
+        update.value = 2;
+        update.unk2 = 4;
+        packet_handler::handle(&bot_mutex, &game_packet(&update)).unwrap();
+
+        assert_eq!(receiver.try_recv(), Ok(Ok(6)));
+        assert!(bot_mutex.lock().unwrap().item_actions.is_empty());
+    }
+
+    #[test]
+    fn equipped_items_are_not_dropped() {
+        let bot_mutex = test_bot();
+        {
+            let mut bot = bot_mutex.lock().unwrap();
+            bot.inventory.size = 16;
+            bot.inventory.items.push(Item {
+                id: 18,
+                amount: 1,
+                flags: 1,
+            });
+        }
+        let (reply, receiver) = mpsc::channel();
+        item_action(
+            &bot_mutex,
+            test_peer(),
+            ItemAction::Trash,
+            18,
+            1,
+            Some(reply),
+        );
+
+        let err = receiver.try_recv().unwrap().unwrap_err();
+        assert!(err.contains("equipped"), "{}", err);
+        assert!(bot_mutex.lock().unwrap().item_actions.is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use std::time::{Duration, Instant};
+
+use gtworld_r::World;
+use spdlog::warn;
+
+use crate::types::bot_info::Position;
+use crate::types::tank_packet_type::TankPacketType;
+
+pub const GEM: u16 = 112;
+
+// NetGamePacketItemChangeObject net ids that aren't players
+const SPAWNED: u32 = u32::MAX;
+const COUNT_CHANGED: u32 = u32::MAX - 2;
+
+#[derive(Debug, Clone)]
+pub struct DroppedObject {
+    pub uid: u32,
+    pub item_id: u16,
+    pub count: u8,
+    // In pixels, like player positions
+    pub position: Position,
+}
+
+// A pickup that was requested. The server removing the object for the bot's net id answers it,
+// the inventory count rising past what it was when asked confirms it.
+#[derive(Debug, Clone)]
+pub struct PendingCollect {
+    pub uid: u32,
+    pub item_id: u16,
+    pub inventory_count: u8,
+    pub sent_at: Instant,
+}
+
+// What's lying on the ground in the current world
+#[derive(Debug, Default)]
+pub struct Objects {
+    // The server numbers drops one after another, a spawn doesn't say which uid it got
+    last_uid: u32,
+    pub items: Vec<DroppedObject>,
+    pending: Vec<PendingCollect>,
+}
+
+impl Objects {
+    pub fn new() -> Objects {
+        Objects {
+            last_uid: 0,
+            items: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    // From the object section of the map data
+    pub fn seed(&mut self, world: &World) {
+        self.last_uid = world.dropped.last_dropped_item_uid;
+        self.items = world
+            .dropped
+            .items
+            .iter()
+            .map(|item| DroppedObject {
+                uid: item.uid,
+                item_id: item.id,
+                count: item.count,
+                position: Position {
+                    x: item.x,
+                    y: item.y,
+                },
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.last_uid = 0;
+        self.items.clear();
+        self.pending.clear();
+    }
+
+    // NetGamePacketItemChangeObject, returns what was picked up and by whom
+    pub fn apply(&mut self, pkt: &TankPacketType) -> Option<(u32, DroppedObject)> {
+        let position = Position {
+            x: pkt.vector_x,
+            y: pkt.vector_y,
+        };
+        // The count is a float in the header field the packet struct calls unk6
+        let count = f32::from_bits(pkt.unk6) as u8;
+        match pkt.net_id {
+            SPAWNED => {
+                self.last_uid = self.last_uid.wrapping_add(1);
+                self.items.push(DroppedObject {
+                    uid: self.last_uid,
+                    item_id: pkt.value as u16,
+                    count,
+                    position,
+                });
+                None
+            }
+            // The packet doesn't carry the uid, only the item and where it lies. Two stacks of
+            // the same item on the same spot can't be told apart, so neither is touched then.
+            COUNT_CHANGED => {
+                let mut matching = self.items.iter_mut().filter(|object| {
+                    object.item_id == pkt.value as u16
+                        && object.position.x == position.x
+                        && object.position.y == position.y
+                });
+                match (matching.next(), matching.next()) {
+                    (Some(object), None) => object.count = count,
+                    (Some(_), Some(_)) => warn!(
+                        "Several drops of item {} at {:?}, not changing their count",
+                        pkt.value, position
+                    ),
+                    _ => (),
+                }
+                None
+            }
+            // A player picked it up, the uid is in the header field after the net id
+            net_id => {
+                let index = self
+                    .items
+                    .iter()
+                    .position(|object| object.uid == pkt.unk4)?;
+                Some((net_id, self.items.remove(index)))
+            }
+        }
+    }
+
+    pub fn get(&self, uid: u32) -> Option<&DroppedObject> {
+        self.items.iter().find(|object| object.uid == uid)
+    }
+
+    // Closest first, radius in tiles
+    pub fn within(&self, from: &Position, radius: f32) -> Vec<&DroppedObject> {
+        let mut objects = self
+            .items
+            .iter()
+            .filter(|object| distance(&object.position, from) <= radius * 32.0)
+            .collect::<Vec<_>>();
+        objects.sort_by(|a, b| distance(&a.position, from).total_cmp(&distance(&b.position, from)));
+        objects
+    }
+
+    pub fn expect_pickup(&mut self, object: &DroppedObject, inventory_count: u8) {
+        self.pending.push(PendingCollect {
+            uid: object.uid,
+            item_id: object.item_id,
+            inventory_count,
+            sent_at: Instant::now(),
+        });
+    }
+
+    pub fn take_pending(&mut self, uid: u32) -> Option<PendingCollect> {
+        let index = self.pending.iter().position(|pending| pending.uid == uid)?;
+        Some(self.pending.remove(index))
+    }
+
+    // Pickups the server never confirmed
+    pub fn expire_pending(&mut self, timeout: Duration) -> Vec<PendingCollect> {
+        let (expired, pending) = self
+            .pending
+            .drain(..)
+            .partition(|pending| pending.sent_at.elapsed() >= timeout);
+        self.pending = pending;
+        expired
+    }
+
+    pub fn of_item(&self, item_id: u16) -> Vec<&DroppedObject> {
+        self.items
+            .iter()
+            .filter(|object| object.item_id == item_id)
+            .collect()
+    }
+
+    pub fn nearest(&self, item_id: u16, from: &Position) -> Option<&DroppedObject> {
+        self.of_item(item_id)
+            .into_iter()
+            .min_by(|a, b| distance(&a.position, from).total_cmp(&distance(&b.position, from)))
+    }
+}
+
+// In pixels
+pub fn distance(a: &Position, b: &Position) -> f32 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use gtitem_r::structs::ItemDatabase;
+    use gtworld_r::DroppedItem;
+
+    use super::*;
+    use crate::types::e_tank_packet_type::ETankPacketType;
+
+    fn change(net_id: u32, uid: u32, item_id: u16, count: u8, x: f32, y: f32) -> TankPacketType {
+        let mut pkt = TankPacketType::new();
+        pkt.packet_type = ETankPacketType::NetGamePacketItemChangeObject;
+        pkt.net_id = net_id;
+        pkt.unk4 = uid;
+        pkt.value = item_id as u32;
+        pkt.unk6 = (count as f32).to_bits();
+        pkt.vector_x = x;
+        pkt.vector_y = y;
+        pkt
+    }
+
+    fn object(uid: u32, item_id: u16, x: f32, y: f32) -> DroppedObject {
+        DroppedObject {
+            uid,
+            item_id,
+            count: 1,
+            position: Position { x, y },
+        }
+    }
+
+    fn uids(objects: &[&DroppedObject]) -> Vec<u32> {
+        objects.iter().map(|object| object.uid).collect()
+    }
+
+    #[test]
+    fn spawns_get_the_next_uid() {
+        let mut objects = Objects::new();
+        objects.last_uid = 7;
+
+        assert!(objects
+            .apply(&change(SPAWNED, 0, GEM, 5, 64.0, 32.0))
+            .is_none());
+        assert!(objects.apply(&change(SPAWNED, 0, 2, 1, 0.0, 0.0)).is_none());
+
+        let gem = objects.get(8).unwrap();
+        assert_eq!((gem.item_id, gem.count), (GEM, 5));
+        assert_eq!((gem.position.x, gem.position.y), (64.0, 32.0));
+        assert_eq!(objects.get(9).unwrap().item_id, 2);
+    }
+
+    #[test]
+    fn uids_wrap_around() {
+        let mut objects = Objects::new();
+        objects.last_uid = u32::MAX;
+
+        objects.apply(&change(SPAWNED, 0, GEM, 1, 0.0, 0.0));
+
+        assert!(objects.get(0).is_some());
+    }
+
+    #[test]
+    fn changes_the_count_of_the_drop_at_that_spot() {
+        let mut objects = Objects::new();
+        objects.items = vec![object(1, GEM, 0.0, 0.0), object(2, GEM, 32.0, 0.0)];
+
+        objects.apply(&change(COUNT_CHANGED, 0, GEM, 9, 32.0, 0.0));
+        // Another item at the same spot isn't it
+        objects.apply(&change(COUNT_CHANGED, 0, 2, 4, 0.0, 0.0));
+
+        assert_eq!(objects.get(1).unwrap().count, 1);
+        assert_eq!(objects.get(2).unwrap().count, 9);
+    }
+
+    #[test]
+    fn leaves_stacks_it_cant_tell_apart() {
+        let mut objects = Objects::new();
+        objects.items = vec![object(1, GEM, 0.0, 0.0), object(2, GEM, 0.0, 0.0)];
+
+        objects.apply(&change(COUNT_CHANGED, 0, GEM, 9, 0.0, 0.0));
+
+        assert_eq!(objects.get(1).unwrap().count, 1);
+        assert_eq!(objects.get(2).unwrap().count, 1);
+    }
+
+    #[test]
+    fn pickups_remove_by_uid() {
+        let mut objects = Objects::new();
+        objects.items = vec![object(1, GEM, 0.0, 0.0), object(2, GEM, 0.0, 0.0)];
+
+        let (net_id, picked) = objects.apply(&change(3, 2, GEM, 0, 0.0, 0.0)).unwrap();
+
+        assert_eq!((net_id, picked.uid), (3, 2));
+        assert_eq!(uids(&objects.of_item(GEM)), vec![1]);
+        // Already gone, or never known
+        assert!(objects.apply(&change(3, 2, GEM, 0, 0.0, 0.0)).is_none());
+        assert!(objects.apply(&change(3, 40, GEM, 0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn seeds_from_the_map_data() {
+        let mut world = World::new(Arc::new(ItemDatabase::new()));
+        world.dropped.last_dropped_item_uid = 4;
+        world.dropped.items = vec![DroppedItem {
+            id: GEM,
+            x: 96.0,
+            y: 64.0,
+            count: 3,
+            flags: 0,
+            uid: 4,
+        }];
+        let mut objects = Objects::new();
+        objects.items = vec![object(1, 2, 0.0, 0.0)];
+
+        objects.seed(&world);
+
+        assert_eq!(objects.items.len(), 1);
+        let gem = objects.get(4).unwrap();
+        assert_eq!((gem.item_id, gem.count), (GEM, 3));
+        assert_eq!((gem.position.x, gem.position.y), (96.0, 64.0));
+        objects.apply(&change(SPAWNED, 0, GEM, 1, 0.0, 0.0));
+        assert!(objects.get(5).is_some());
+    }
+
+    #[test]
+    fn within_is_closest_first_and_in_tiles() {
+        let mut objects = Objects::new();
+        objects.items = vec![
+            object(1, GEM, 64.0, 0.0),
+            object(2, 2, 32.0, 0.0),
+            object(3, GEM, 0.0, 96.0),
+            object(4, GEM, 0.0, 0.0),
+        ];
+        let from = Position { x: 0.0, y: 0.0 };
+
+        assert_eq!(uids(&objects.within(&from, 2.0)), vec![4, 2, 1]);
+        assert_eq!(uids(&objects.within(&from, 3.0)), vec![4, 2, 1, 3]);
+        assert_eq!(uids(&objects.within(&from, 0.0)), vec![4]);
+    }
+
+    #[test]
+    fn finds_the_nearest_of_an_item() {
+        let mut objects = Objects::new();
+        objects.items = vec![
+            object(1, GEM, 96.0, 0.0),
+            object(2, 2, 0.0, 0.0),
+            object(3, GEM, 32.0, 32.0),
+        ];
+        let from = Position { x: 0.0, y: 0.0 };
+
+        assert_eq!(uids(&objects.of_item(GEM)), vec![1, 3]);
+        assert_eq!(objects.nearest(GEM, &from).unwrap().uid, 3);
+        assert_eq!(objects.nearest(2, &from).unwrap().uid, 2);
+        assert!(objects.nearest(4, &from).is_none());
+    }
+}
@@ -1,74 +1,216 @@
 use std::sync::{Arc, Mutex};
 
-use crate::bot::{disconnect, send_packet};
+use crate::bot::{disconnect, send_packet, send_tank_packet};
+use crate::types::reconnect_policy::DisconnectReason;
 use crate::types::{
-    e_packet_type::EPacketType, e_tank_packet_type::ETankPacketType,
+    bot_state::BotState,
+    e_packet_type::EPacketType,
+    e_tank_packet_type::ETankPacketType,
+    packet_error::PacketError,
     tank_packet_type::TankPacketType,
+    text_packet::TextPacket,
+    tile_update::{TileUpdate, FIST},
 };
-use crate::utils::bytes;
+use crate::utils::variant::VariantList;
 
+use super::objects::{DroppedObject, PendingCollect, GEM};
+use super::variant_handler;
 use super::Bot;
-use super::{variant_handler, ENET_HOST};
-use enet::{Packet, PacketMode, PeerID};
-use spdlog::info;
+use byteorder::{ByteOrder, LittleEndian};
+use spdlog::{info, warn};
 
-pub fn handle(bot_mutex: &Arc<Mutex<Bot>>, packet_type: EPacketType, data: &[u8]) {
+// Item action type of backgrounds, placing one doesn't touch the foreground
+const BACKGROUND_ACTION_TYPE: u8 = 18;
+
+pub fn parse_message(data: &[u8]) -> Result<(EPacketType, &[u8]), PacketError> {
+    if data.len() < 4 {
+        return Err(PacketError::TooShort {
+            expected: 4,
+            actual: data.len(),
+        });
+    }
+    let packet_id = LittleEndian::read_u32(&data[0..4]);
+    Ok((EPacketType::from(packet_id), &data[4..]))
+}
+
+// One line per packet, used by the packet inspector and the ENet proxy log
+pub fn summarize(data: &[u8]) -> String {
+    let (packet_type, payload) = match parse_message(data) {
+        Ok(message) => message,
+        Err(err) => return err.to_string(),
+    };
+    match packet_type {
+        EPacketType::NetMessageGenericText | EPacketType::NetMessageGameMessage => {
+            let text = String::from_utf8_lossy(payload);
+            let first_line = text.lines().next().unwrap_or_default().to_string();
+            format!("{:?} {}", packet_type, first_line)
+        }
+        EPacketType::NetMessageGamePacket => match TankPacketType::deserialize(payload) {
+            Ok(pkt) if pkt.packet_type == ETankPacketType::NetGamePacketCallFunction => {
+                let function = VariantList::deserialize(&pkt.extended_data)
+                    .ok()
+                    .and_then(|variants| variants.get(0).map(|variant| variant.as_string()))
+                    .unwrap_or_default();
+                format!("{:?} {}", pkt.packet_type, function)
+            }
+            Ok(pkt) => format!("{:?}", pkt.packet_type),
+            Err(_) => format!("{:?} (malformed)", packet_type),
+        },
+        _ => format!("{:?}", packet_type),
+    }
+}
+
+pub fn handle(bot_mutex: &Arc<Mutex<Bot>>, data: &[u8]) -> Result<(), PacketError> {
+    let (packet_type, data) = parse_message(data)?;
     match packet_type {
         EPacketType::NetMessageServerHello => {
             info!("Received NetMessageServerHello");
             let bot = bot_mutex.lock().unwrap();
-            if bot.state.is_redirect {
-                let message = format!(
-                    "UUIDToken|{}\nprotocol|{}\nfhash|{}\nmac|{}\nrequestedName|{}\nhash2|{}\nfz|{}\nf|{}\nplayer_age|{}\ngame_version|{}\nlmode|{}\ncbits|{}\nrid|{}\nGDPR|{}\nhash|{}\ncategory|{}\ntoken|{}\ntotal_playtime|{}\ndoor_id|{}\nklv|{}\nmeta|{}\nplatformID|{}\ndeviceVersion|{}\nzf|{}\ncountry|{}\nuser|{}\nwk|{}\n",
-                    bot.info.login_info.uuid, bot.info.login_info.protocol, bot.info.login_info.fhash, bot.info.login_info.mac, bot.info.login_info.requested_name, bot.info.login_info.hash2, bot.info.login_info.fz, bot.info.login_info.f, bot.info.login_info.player_age, bot.info.login_info.game_version, bot.info.login_info.lmode, bot.info.login_info.cbits, bot.info.login_info.rid, bot.info.login_info.gdpr, bot.info.login_info.hash, bot.info.login_info.category, bot.info.login_info.token, bot.info.login_info.total_playtime, bot.info.login_info.door_id, bot.info.login_info.klv, bot.info.login_info.meta, bot.info.login_info.platform_id, bot.info.login_info.device_version, bot.info.login_info.zf, bot.info.login_info.country, bot.info.login_info.user, bot.info.login_info.wk
-                );
-                let peer_id = bot.peer_id.unwrap().clone();
-                send_packet(peer_id, EPacketType::NetMessageGenericText, message);
+            let message = if bot.state.current() == BotState::Redirecting {
+                TextPacket::from(&bot.info.login_info).serialize()
             } else {
-                let message = format!(
-                    "protocol|{}\nltoken|{}\nplatformID|{}\n",
-                    209, bot.info.token, "0,1,1"
-                );
-                let peer_id = bot.peer_id.unwrap();
-                send_packet(peer_id, EPacketType::NetMessageGenericText, message);
-            }
+                TextPacket::new()
+                    .with("protocol", 209)
+                    .with("ltoken", &bot.info.token)
+                    .with("platformID", "0,1,1")
+                    .serialize()
+            };
+            let Some(peer_id) = bot.peer_id else {
+                warn!("Received NetMessageServerHello without a peer, not logging in");
+                return Ok(());
+            };
+            send_packet(peer_id, EPacketType::NetMessageGenericText, message);
         }
         EPacketType::NetMessageGenericText => {
             info!("Received NetMessageGenericText");
+            let message = TextPacket::deserialize(&String::from_utf8_lossy(data));
+            if let Some(action) = message.get("action") {
+                info!("Action: {}", action);
+            }
         }
         EPacketType::NetMessageGameMessage => {
+            let message = data.get(4..).ok_or(PacketError::TooShort {
+                expected: 4,
+                actual: data.len(),
+            })?;
+            let message = String::from_utf8_lossy(message);
             let mut bot = bot_mutex.lock().unwrap();
-            let message = String::from_utf8_lossy(&data[4..]);
             info!("Received NetMessageGameMessage");
             info!("Message: {}", message);
 
-            if message.contains("logon_fail") {
-                bot.state.is_redirect = false;
-                let peer_id = bot.peer_id.unwrap();
-                disconnect(peer_id);
+            // The reason usually comes in a log message right before logon_fail
+            let reason = DisconnectReason::classify(&message);
+            if reason.is_some() {
+                bot.state.disconnect_reason = reason;
             }
-            if message.contains("currently banned") {
-                bot.state.is_banned = true;
-                bot.state.is_running = false;
-                let peer_id = bot.peer_id.unwrap();
-                disconnect(peer_id);
+
+            if message.contains("logon_fail") || reason == Some(DisconnectReason::Banned) {
+                let reason = *bot
+                    .state
+                    .disconnect_reason
+                    .get_or_insert(DisconnectReason::NetworkError);
+                info!("Logon failed: {}", reason);
+                if reason == DisconnectReason::Banned {
+                    bot.state.transition(BotState::Banned);
+                } else {
+                    bot.state.transition(BotState::Reconnecting);
+                }
+                if let Some(peer_id) = bot.peer_id {
+                    disconnect(peer_id);
+                }
             }
         }
         EPacketType::NetMessageGamePacket => {
-            let tank_packet = map_slice_to_tank_packet_type(data);
+            let tank_packet = TankPacketType::deserialize(data)?;
             info!("Received Tank packet type: {:?}", tank_packet.packet_type);
 
             if tank_packet.packet_type == ETankPacketType::NetGamePacketCallFunction {
-                variant_handler::handle(&bot_mutex, &tank_packet, &data[56..]);
+                variant_handler::handle(bot_mutex, &tank_packet, &tank_packet.extended_data)?;
             }
             if tank_packet.packet_type == ETankPacketType::NetGamePacketSendMapData {
                 let mut bot = bot_mutex.lock().unwrap();
-                bot.world.parse(&data[56..]);
-                bot.astar.update(&bot_mutex);
+                let bot = &mut *bot;
+                // Everyone in the new world is spawned again after the map
+                bot.players.clear();
+                bot.world.parse(&tank_packet.extended_data);
+                bot.astar.update(&bot.world);
+                bot.objects.seed(&bot.world);
+            }
+            if tank_packet.packet_type == ETankPacketType::NetGamePacketTileChangeRequest {
+                let mut bot = bot_mutex.lock().unwrap();
+                apply_tile_change(&mut bot, &tank_packet);
+            }
+            if tank_packet.packet_type == ETankPacketType::NetGamePacketSendTileUpdateData {
+                let update = TileUpdate::from_packet(&tank_packet)?;
+                let mut bot = bot_mutex.lock().unwrap();
+                apply_tile_update(&mut bot, &update);
+            }
+            if tank_packet.packet_type == ETankPacketType::NetGamePacketSendTileUpdateDataMultiple {
+                let updates = TileUpdate::parse_multiple(&tank_packet.extended_data)?;
+                let mut bot = bot_mutex.lock().unwrap();
+                for update in &updates {
+                    apply_tile_update(&mut bot, update);
+                }
+            }
+            if tank_packet.packet_type == ETankPacketType::NetGamePacketItemChangeObject {
+                let mut bot = bot_mutex.lock().unwrap();
+                if let Some((net_id, object)) = bot.objects.apply(&tank_packet) {
+                    info!(
+                        "{} picked up {}x item {}",
+                        net_id, object.count, object.item_id
+                    );
+                    if net_id == bot.state.net_id {
+                        let pending = bot.objects.take_pending(object.uid);
+                        // Gems aren't kept in the inventory, OnSetBux tracks them
+                        if object.item_id == GEM {
+                            if pending.is_some() {
+                                info!("Collected {} gems", object.count);
+                            }
+                        } else {
+                            let added = bot.inventory.add(object.item_id, object.count);
+                            match pending {
+                                Some(pending) => report_pickup(&bot, &object, &pending),
+                                None if added < object.count => warn!(
+                                    "Only {} of {}x item {} fit in the inventory",
+                                    added, object.count, object.item_id
+                                ),
+                                None => (),
+                            }
+                        }
+                    }
+                }
+            }
+            if tank_packet.packet_type == ETankPacketType::NetGamePacketModifyItemInventory {
+                let item_id = tank_packet.value as u16;
+                let mut bot = bot_mutex.lock().unwrap();
+                let removed = tank_packet.removed_amount();
+                if removed > 0 {
+                    bot.inventory.remove(item_id, removed);
+                    let left = bot.inventory.count(item_id);
+                    if let Some(action) = bot.item_actions.confirm(item_id, removed, left) {
+                        info!(
+                            "The {} of {}x item {} was confirmed, {} left",
+                            action.name(),
+                            removed,
+                            item_id,
+                            left
+                        );
+                    }
+                }
+                if tank_packet.added_amount() > 0 {
+                    bot.inventory.add(item_id, tank_packet.added_amount());
+                }
+            }
+            if tank_packet.packet_type == ETankPacketType::NetGamePacketState {
+                let mut bot = bot_mutex.lock().unwrap();
+                if let Some(player) = bot.players.get_mut(&tank_packet.net_id) {
+                    player.position.x = tank_packet.vector_x;
+                    player.position.y = tank_packet.vector_y;
+                }
             }
             if tank_packet.packet_type == ETankPacketType::NetGamePacketSendInventoryState {
                 let mut bot = bot_mutex.lock().unwrap();
-                bot.inventory.parse(&data[56..]);
+                bot.inventory.parse(&tank_packet.extended_data)?;
             }
             if tank_packet.packet_type == ETankPacketType::NetGamePacketPingRequest {
                 let mut pkt = TankPacketType::new();
@@ -80,35 +222,12 @@ pub fn handle(bot_mutex: &Arc<Mutex<Bot>>, packet_type: EPacketType, data: &[u8]
                 pkt.vector_x2 = 1000.0;
                 pkt.vector_y2 = 250.0;
 
-                let mut packet_data = Vec::new();
-                packet_data
-                    .extend_from_slice(&(EPacketType::NetMessageGamePacket as u32).to_le_bytes());
-                packet_data.extend_from_slice(&(pkt.packet_type as u8).to_le_bytes());
-                packet_data.extend_from_slice(&pkt.unk1.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.unk2.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.unk3.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.net_id.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.unk4.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.flags.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.unk6.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.value.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.vector_x.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.vector_y.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.vector_x2.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.vector_y2.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.unk12.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.int_x.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.int_y.to_le_bytes());
-                packet_data.extend_from_slice(&pkt.extended_data_length.to_le_bytes());
-                packet_data.extend_from_slice(&data[56..]);
-
-                let pkt = Packet::new(packet_data, PacketMode::ReliableSequenced).unwrap();
-                ENET_HOST.with_borrow_mut(|enet_host| {
-                    let enet_host = enet_host.as_mut().unwrap();
-                    let peer_id = bot_mutex.lock().unwrap().peer_id.unwrap();
-                    let peer = enet_host.peer_mut(peer_id).unwrap();
-                    peer.send_packet(pkt, 0).unwrap();
-                });
+                pkt.extended_data = tank_packet.extended_data.clone();
+
+                let Some(peer_id) = bot_mutex.lock().unwrap().peer_id else {
+                    return Ok(());
+                };
+                send_tank_packet(peer_id, &pkt);
             }
         }
         EPacketType::NetMessageError => {
@@ -130,26 +249,84 @@ pub fn handle(bot_mutex: &Arc<Mutex<Bot>>, packet_type: EPacketType, data: &[u8]
         }
         _ => (),
     }
+    Ok(())
+}
+
+// Someone placed or broke a block, the server only sends the item that was used
+// A requested pickup only counts once the inventory holds more of the item than when it was asked
+fn report_pickup(bot: &Bot, object: &DroppedObject, pending: &PendingCollect) {
+    let gained = bot
+        .inventory
+        .count(object.item_id)
+        .saturating_sub(pending.inventory_count);
+    if gained == 0 {
+        warn!(
+            "Picking up object {} failed, no room for item {}",
+            object.uid, object.item_id
+        );
+    } else if gained < object.count {
+        warn!(
+            "Only picked up {} of {}x item {}",
+            gained, object.count, object.item_id
+        );
+    } else {
+        info!("Collected {}x item {}", object.count, object.item_id);
+    }
+}
+
+fn apply_tile_change(bot: &mut Bot, pkt: &TankPacketType) {
+    let (Ok(x), Ok(y)) = (u32::try_from(pkt.int_x), u32::try_from(pkt.int_y)) else {
+        return;
+    };
+    let Some(index) = tile_index(bot, x, y) else {
+        warn!("Tile change outside of the world: {}, {}", x, y);
+        return;
+    };
+    let item_id = pkt.value as u16;
+    let is_background = bot
+        .world
+        .item_database
+        .get_item(&(item_id as u32))
+        .is_some_and(|item| item.action_type == BACKGROUND_ACTION_TYPE);
+
+    let tile = &mut bot.world.tiles[index];
+    if item_id == FIST {
+        // A broken tile loses its foreground first, then its background
+        if tile.foreground_item_id != 0 {
+            tile.foreground_item_id = 0;
+        } else {
+            tile.background_item_id = 0;
+        }
+    } else if is_background {
+        tile.background_item_id = item_id;
+    } else {
+        tile.foreground_item_id = item_id;
+    }
+    let foreground_item_id = tile.foreground_item_id;
+    bot.astar.update_tile(x, y, foreground_item_id);
+}
+
+fn apply_tile_update(bot: &mut Bot, update: &TileUpdate) {
+    let Some(index) = tile_index(bot, update.x, update.y) else {
+        warn!(
+            "Tile update outside of the world: {}, {}",
+            update.x, update.y
+        );
+        return;
+    };
+    let tile = &mut bot.world.tiles[index];
+    tile.foreground_item_id = update.foreground_item_id;
+    tile.background_item_id = update.background_item_id;
+    tile.parent_block_index = update.parent_block_index;
+    tile.flags = update.flags;
+    bot.astar
+        .update_tile(update.x, update.y, update.foreground_item_id);
 }
 
-fn map_slice_to_tank_packet_type(data: &[u8]) -> TankPacketType {
-    TankPacketType {
-        packet_type: ETankPacketType::from(data[0]),
-        unk1: data[1],
-        unk2: data[2],
-        unk3: data[3],
-        net_id: bytes::bytes_to_u32(&data[4..8]),
-        unk4: bytes::bytes_to_u32(&data[8..12]),
-        flags: bytes::bytes_to_u32(&data[12..16]),
-        unk6: bytes::bytes_to_u32(&data[16..20]),
-        value: bytes::bytes_to_u32(&data[20..24]),
-        vector_x: bytes::bytes_to_f32(&data[24..28]),
-        vector_y: bytes::bytes_to_f32(&data[28..32]),
-        vector_x2: bytes::bytes_to_f32(&data[32..36]),
-        vector_y2: bytes::bytes_to_f32(&data[36..40]),
-        unk12: bytes::bytes_to_f32(&data[40..44]),
-        int_x: bytes::bytes_to_i32(&data[44..48]),
-        int_y: bytes::bytes_to_i32(&data[48..52]),
-        extended_data_length: bytes::bytes_to_u32(&data[52..56]),
+fn tile_index(bot: &Bot, x: u32, y: u32) -> Option<usize> {
+    if x >= bot.world.width || y >= bot.world.height {
+        return None;
     }
+    let index = (y * bot.world.width + x) as usize;
+    (index < bot.world.tiles.len()).then_some(index)
 }
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::net::SocketAddrV4;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+use enet::*;
+use spdlog::{error, info, warn};
+
+use crate::types::bot_state::BotState;
+use crate::types::reconnect_policy::DisconnectReason;
+use crate::utils::socks5::UdpRelay;
+
+use super::capture::{self, Direction, Tap};
+use super::supervisor::{isolate, record_packet, spawn_isolated};
+use super::{
+    connect, disconnect, expire_pending, packet_handler, process_commands, set_ping, Bot, ENET_HOST,
+};
+
+// Peers a single host can hold, one per bot
+const MAX_PEERS: usize = 256;
+// Kept short so queued commands don't wait long for the next loop
+const SERVICE_TIMEOUT: Duration = Duration::from_millis(10);
+
+struct Peer {
+    bot: Arc<Mutex<Bot>>,
+    // Set once the peer was told to disconnect, ENet only needs to hear it once
+    disconnecting: bool,
+}
+
+impl Peer {
+    fn new(bot: Arc<Mutex<Bot>>) -> Peer {
+        Peer {
+            bot,
+            disconnecting: false,
+        }
+    }
+}
+
+// One network thread that services the ENet peers of many bots.
+// Events are routed to the bot that owns the PeerID they came from.
+#[derive(Clone)]
+pub struct Reactor {
+    sender: Sender<Arc<Mutex<Bot>>>,
+    load: Arc<AtomicUsize>,
+}
+
+impl Reactor {
+    // Waits for the thread to create its host, so a failure reaches whoever built the pool
+    pub fn spawn(enet: Enet) -> Result<Reactor, String> {
+        let (sender, receiver) = mpsc::channel();
+        let reactor = Reactor {
+            sender,
+            load: Arc::new(AtomicUsize::new(0)),
+        };
+        let reactor_clone = reactor.clone();
+        let (started_sender, started) = mpsc::channel();
+        spawn(move || run(enet, receiver, reactor_clone, started_sender));
+        started
+            .recv()
+            .map_err(|_| "Reactor thread exited before starting".to_string())??;
+        Ok(reactor)
+    }
+
+    // Hands over a bot that is ready to connect, see bot::connect
+    pub fn submit(&self, bot_mutex: Arc<Mutex<Bot>>) {
+        if self.sender.send(bot_mutex).is_err() {
+            error!("Reactor thread is gone, dropping bot");
+        }
+    }
+
+    pub fn load(&self) -> usize {
+        self.load.load(Ordering::Relaxed)
+    }
+}
+
+pub struct ReactorPool {
+    reactors: Vec<Reactor>,
+}
+
+impl ReactorPool {
+    pub fn new(size: usize) -> Result<ReactorPool, String> {
+        let enet = Enet::new().map_err(|err| format!("Failed to initialize ENet: {:?}", err))?;
+        ReactorPool::with_enet(enet, size)
+    }
+
+    pub fn with_enet(enet: Enet, size: usize) -> Result<ReactorPool, String> {
+        let reactors = (0..size.max(1))
+            .map(|_| Reactor::spawn(enet.clone()))
+            .collect::<Result<Vec<Reactor>, String>>()?;
+        Ok(ReactorPool { reactors })
+    }
+
+    // A bot should stay on the reactor it got, its PeerID only means something on that host
+    pub fn least_loaded(&self) -> Reactor {
+        self.reactors
+            .iter()
+            .min_by_key(|reactor| reactor.load())
+            .unwrap()
+            .clone()
+    }
+}
+
+fn run(
+    enet: Enet,
+    receiver: Receiver<Arc<Mutex<Bot>>>,
+    reactor: Reactor,
+    started: Sender<Result<(), String>>,
+) {
+    let host = Enet::create_host::<()>(
+        &enet,
+        None,
+        MAX_PEERS,
+        ChannelLimit::Limited(1),
+        BandwidthLimit::Unlimited,
+        BandwidthLimit::Unlimited,
+        true,
+        false,
+    );
+    match host {
+        Ok(host) => {
+            ENET_HOST.set(Some(host));
+            let _ = started.send(Ok(()));
+        }
+        Err(err) => {
+            let _ = started.send(Err(format!("Failed to create ENet host: {}", err)));
+            return;
+        }
+    }
+
+    let mut peers: HashMap<PeerID, Peer> = HashMap::new();
+    loop {
+        // Nothing to service, so block until a bot shows up
+        if peers.is_empty() {
+            match receiver.recv() {
+                Ok(bot_mutex) => accept(bot_mutex, &mut peers, &reactor),
+                Err(_) => break,
+            }
+        }
+        loop {
+            match receiver.try_recv() {
+                Ok(bot_mutex) => accept(bot_mutex, &mut peers, &reactor),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        for (peer_id, peer) in peers.iter_mut() {
+            // A bot the GUI is holding gets its turn on the next pass instead of stalling the others
+            let (stale, running, has_commands, tap) = match peer.bot.try_lock() {
+                Ok(mut bot) => {
+                    expire_pending(&mut bot);
+                    (
+                        bot.peer_id != Some(*peer_id),
+                        bot.state.current().is_running(),
+                        !bot.command_queue.is_empty(),
+                        Tap {
+                            recorder: bot.capture.clone(),
+                            log: Arc::clone(&bot.packet_log),
+                        },
+                    )
+                }
+                Err(_) => continue,
+            };
+            capture::track(*peer_id, if stale { None } else { Some(tap) });
+            if !stale && has_commands {
+                isolate(&peer.bot, &reactor, || process_commands(&peer.bot));
+            }
+            // Restarted or stopped bots leave their old peer behind, it's closed here
+            if (stale || !running) && !peer.disconnecting {
+                disconnect(*peer_id);
+                peer.disconnecting = true;
+            }
+        }
+
+        // Waits for the first event, then takes whatever else already arrived
+        let mut event = get_event(true);
+        while let Some((peer_id, kind)) = event {
+            if let Some(bot_mutex) = peers.get(&peer_id).map(|peer| Arc::clone(&peer.bot)) {
+                isolate(&bot_mutex, &reactor, || {
+                    handle_event(&bot_mutex, peer_id, kind, &mut peers, &reactor)
+                });
+            }
+            event = get_event(false);
+        }
+        reactor.load.store(peers.len(), Ordering::Relaxed);
+    }
+}
+
+fn get_event(wait: bool) -> Option<(PeerID, EventKind)> {
+    ENET_HOST.with_borrow_mut(|enet_host| {
+        let enet_host = enet_host.as_mut().unwrap();
+        let event = if wait {
+            enet_host.service(SERVICE_TIMEOUT)
+        } else {
+            enet_host.check_events()
+        };
+        match event {
+            Ok(Some(event)) => Some((event.peer_id(), event.take_kind())),
+            Ok(None) => None,
+            Err(err) => {
+                error!("Service failed: {}", err);
+                None
+            }
+        }
+    })
+}
+
+fn handle_event(
+    bot_mutex: &Arc<Mutex<Bot>>,
+    peer_id: PeerID,
+    event: EventKind,
+    peers: &mut HashMap<PeerID, Peer>,
+    reactor: &Reactor,
+) {
+    if bot_mutex.lock().unwrap().peer_id != Some(peer_id) {
+        if let EventKind::Disconnect { .. } = event {
+            peers.remove(&peer_id);
+            capture::track(peer_id, None);
+        }
+        return;
+    }
+
+    match event {
+        EventKind::Connect => {
+            info!("Connected to the server");
+        }
+        EventKind::Disconnect { .. } => {
+            peers.remove(&peer_id);
+            capture::track(peer_id, None);
+            let state = {
+                let mut bot = bot_mutex.lock().unwrap();
+                info!("Disconnected from the server");
+                bot.peer_id = None;
+                bot.proxy_relay = None;
+                bot.item_actions.clear("disconnected");
+                // Redirects, stops and logon failures are decided by whoever triggered the disconnect
+                if !matches!(
+                    bot.state.current(),
+                    BotState::Redirecting
+                        | BotState::Reconnecting
+                        | BotState::Banned
+                        | BotState::Stopped
+                ) {
+                    bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+                    bot.state.transition(BotState::Reconnecting);
+                }
+                bot.state.current()
+            };
+            match state {
+                BotState::Redirecting => connect_peer(Arc::clone(bot_mutex), peers, reactor),
+                // Waiting out the backoff and fetching server data would stall every other bot
+                BotState::Reconnecting => spawn_connect(bot_mutex, reactor),
+                _ => (),
+            }
+        }
+        EventKind::Receive { packet, .. } => {
+            capture::record(peer_id, Direction::Inbound, packet.data());
+            record_packet(bot_mutex, packet.data());
+            set_ping(bot_mutex);
+            if let Err(err) = packet_handler::handle(bot_mutex, packet.data()) {
+                warn!("Skipping bad packet: {}", err);
+            }
+        }
+    }
+}
+
+fn accept(bot_mutex: Arc<Mutex<Bot>>, peers: &mut HashMap<PeerID, Peer>, reactor: &Reactor) {
+    let bot_clone = Arc::clone(&bot_mutex);
+    isolate(&bot_clone, reactor, || {
+        connect_peer(bot_mutex, peers, reactor)
+    });
+}
+
+fn connect_peer(bot_mutex: Arc<Mutex<Bot>>, peers: &mut HashMap<PeerID, Peer>, reactor: &Reactor) {
+    let mut bot = bot_mutex.lock().unwrap();
+    let (ip, port) = match bot.state.current() {
+        BotState::Redirecting => (bot.server.ip.clone(), bot.server.port.clone()),
+        // Server data without them fails as an invalid address below and is fetched again
+        BotState::Connecting => (
+            bot.info
+                .parsed_server_data
+                .get("server")
+                .cloned()
+                .unwrap_or_default(),
+            bot.info
+                .parsed_server_data
+                .get("port")
+                .cloned()
+                .unwrap_or_default(),
+        ),
+        _ => return,
+    };
+
+    let peer_id = match (ip.parse(), port.parse()) {
+        (Ok(ip), Ok(port)) => {
+            let mut target = SocketAddrV4::new(ip, port);
+            match &bot.info.proxy {
+                Some(proxy) if proxy.supports_udp() => {
+                    match &bot.proxy_relay {
+                        Some(relay) if relay.target() == target => target = relay.local_addr(),
+                        // The SOCKS handshake blocks, it's done on its own thread and the bot comes back here after
+                        _ => {
+                            drop(bot);
+                            spawn_open_relay(&bot_mutex, target, reactor);
+                            return;
+                        }
+                    }
+                }
+                // Going around the proxy would give away the address it's meant to hide
+                Some(proxy) => {
+                    error!("{} can't carry UDP, not connecting to the server", proxy);
+                    bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+                    bot.state.transition(BotState::Stopped);
+                    return;
+                }
+                None => (),
+            }
+            info!("Connecting to {}:{}", ip, port);
+            ENET_HOST.with_borrow_mut(|enet_host| {
+                let enet_host = enet_host.as_mut().unwrap();
+                enet_host
+                    .connect(&Address::new(*target.ip(), target.port()), 2, 0)
+                    .map_err(|err| err.to_string())
+            })
+        }
+        _ => Err(format!("invalid address {}:{}", ip, port)),
+    };
+    match peer_id {
+        Ok(peer_id) => {
+            bot.peer_id = Some(peer_id);
+            drop(bot);
+            peers.insert(peer_id, Peer::new(bot_mutex));
+        }
+        Err(err) => {
+            error!("Failed to connect to the server: {}", err);
+            bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+            bot.state.transition(BotState::Reconnecting);
+            drop(bot);
+            spawn_connect(&bot_mutex, reactor);
+        }
+    }
+}
+
+fn spawn_open_relay(bot_mutex: &Arc<Mutex<Bot>>, target: SocketAddrV4, reactor: &Reactor) {
+    let bot_clone = Arc::clone(bot_mutex);
+    let reactor_clone = reactor.clone();
+    spawn_isolated(Arc::clone(bot_mutex), reactor.clone(), move || {
+        let proxy = match bot_clone.lock().unwrap().info.proxy.clone() {
+            Some(proxy) => proxy,
+            None => return,
+        };
+        info!("Opening a UDP relay through {} for {}", proxy, target);
+        match UdpRelay::open(&proxy, target) {
+            Ok(relay) => {
+                bot_clone.lock().unwrap().proxy_relay = Some(relay);
+                reactor_clone.submit(bot_clone);
+            }
+            Err(err) => {
+                error!("Failed to open a UDP relay through {}: {}", proxy, err);
+                {
+                    let mut bot = bot_clone.lock().unwrap();
+                    bot.state.disconnect_reason = Some(DisconnectReason::NetworkError);
+                    bot.state.transition(BotState::Reconnecting);
+                }
+                connect(&bot_clone, &reactor_clone);
+            }
+        }
+    });
+}
+
+fn spawn_connect(bot_mutex: &Arc<Mutex<Bot>>, reactor: &Reactor) {
+    let bot_clone = Arc::clone(bot_mutex);
+    let reactor_clone = reactor.clone();
+    spawn_isolated(Arc::clone(bot_mutex), reactor.clone(), move || {
+        connect(&bot_clone, &reactor_clone)
+    });
+}
@@ -0,0 +1,91 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use spdlog::{info, warn};
+
+use crate::types::bot_state::BotState;
+use crate::types::e_login_method::ELoginMethod;
+use crate::types::reconnect_policy::ReconnectPolicy;
+
+use super::capture::{self, Direction};
+use super::supervisor::record_packet;
+use super::{packet_handler, Bot};
+
+// Feeds the inbound packets of a capture through the packet handler, offline.
+// Nothing is sent anywhere: the bot never gets a peer, so handlers skip their sends and disconnects.
+// Run with `Mori --replay <capture>`.
+pub fn run(capture_path: &str) -> Result<(), String> {
+    let records = capture::read(capture_path)
+        .map_err(|err| format!("Failed to read {}: {}", capture_path, err))?;
+    let item_database = gtitem_r::load_from_file("items.dat")
+        .map_err(|err| format!("Failed to load items.dat: {}", err))?;
+
+    let mut bot = Bot::new(
+        "replay".to_string(),
+        String::new(),
+        String::new(),
+        ELoginMethod::LEGACY,
+        ReconnectPolicy::default(),
+        None,
+        Arc::new(item_database),
+    );
+    // A capture starts at the first connect, after the server data was fetched
+    bot.state.transition(BotState::FetchingServerData);
+    bot.state.transition(BotState::Connecting);
+    let bot_mutex = Arc::new(Mutex::new(bot));
+
+    let mut replayed = 0;
+    let mut errors = 0;
+    for (index, record) in records.iter().enumerate() {
+        if record.direction == Direction::Outbound {
+            info!(
+                "#{} {:?} sent {} bytes: {}",
+                index,
+                record.elapsed,
+                record.data.len(),
+                preview(&record.data)
+            );
+            continue;
+        }
+        info!(
+            "#{} {:?} received {} bytes: {}",
+            index,
+            record.elapsed,
+            record.data.len(),
+            preview(&record.data)
+        );
+        record_packet(&bot_mutex, &record.data);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            packet_handler::handle(&bot_mutex, &record.data)
+        }));
+        replayed += 1;
+        match result {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => {
+                errors += 1;
+                warn!("#{} Bad packet: {}", index, err);
+            }
+            Err(_) => {
+                return Err(format!(
+                    "Packet #{} at {:?} panicked the handler, see the panic message above",
+                    index, record.elapsed
+                ));
+            }
+        }
+    }
+
+    let bot = bot_mutex.lock().unwrap();
+    info!(
+        "Replayed {} inbound packets, {} bad. State: {}, world: {:?}, inventory: {} items",
+        replayed,
+        errors,
+        bot.state.current(),
+        bot.world.name,
+        bot.inventory.items.len()
+    );
+    Ok(())
+}
+
+fn preview(data: &[u8]) -> String {
+    hex::encode(&data[..data.len().min(32)])
+}
@@ -0,0 +1,119 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use spdlog::{error, info};
+
+use crate::types::bot_state::BotState;
+use crate::types::crash_report::CrashReport;
+
+use super::reactor::Reactor;
+use super::{login, wait_while, Bot};
+
+// How many received packets are kept around for crash reports
+const RECENT_PACKETS: usize = 16;
+// Only the start of each packet is logged, world data can be huge
+const LOGGED_PACKET_BYTES: usize = 64;
+
+// Runs bot code so that a panic only takes down the bot that caused it, not the thread
+// (and the other bots on a reactor) it was running on. Returns false if it panicked.
+pub fn isolate<F: FnOnce()>(bot_mutex: &Arc<Mutex<Bot>>, reactor: &Reactor, f: F) -> bool {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(()) => true,
+        Err(payload) => {
+            crashed(bot_mutex, reactor, payload);
+            false
+        }
+    }
+}
+
+fn crashed(bot_mutex: &Arc<Mutex<Bot>>, reactor: &Reactor, payload: Box<dyn Any + Send>) {
+    // The panic may have happened while the bot was locked, the GUI would panic on it next
+    bot_mutex.clear_poison();
+
+    let mut bot = bot_mutex.lock().unwrap();
+    let report = CrashReport {
+        message: panic_message(payload.as_ref()),
+        state: bot.state.current(),
+        recent_packets: bot.recent_packets.iter().cloned().collect(),
+        crashed_at: Instant::now(),
+    };
+    error!(
+        "Bot {} crashed while {}: {}",
+        bot.info.username, report.state, report.message
+    );
+    for packet in &report.recent_packets {
+        error!(
+            "Recent packet ({} bytes): {}",
+            packet.len(),
+            hex::encode(&packet[..packet.len().min(LOGGED_PACKET_BYTES)])
+        );
+    }
+    bot.state.crash = Some(report);
+    bot.state.crash_count += 1;
+    bot.state.transition(BotState::Crashed);
+
+    let policy = bot.info.reconnect_policy.clone();
+    let crash_count = bot.state.crash_count;
+    drop(bot);
+    if policy.restart_on_crash && (policy.max_retries == 0 || crash_count <= policy.max_retries) {
+        let handle = {
+            let bot_mutex = Arc::clone(bot_mutex);
+            let reactor = reactor.clone();
+            spawn(move || {
+                let delay = Duration::from_millis(policy.base_delay_ms);
+                // Stopping or restarting the bot by hand cancels this
+                if wait_while(&bot_mutex, BotState::Crashed, delay) {
+                    info!("Restarting crashed bot (crash {})", crash_count);
+                    bot_mutex.lock().unwrap().reset();
+                    let bot_clone = Arc::clone(&bot_mutex);
+                    let reactor_clone = reactor.clone();
+                    isolate(&bot_mutex, &reactor, move || {
+                        login(bot_clone, reactor_clone)
+                    });
+                }
+            })
+        };
+        track(bot_mutex, handle);
+    }
+}
+
+// Spawns a thread for bot code with its panics isolated, the bot keeps its handle.
+// The bot must not be locked by the caller.
+pub fn spawn_isolated<F>(bot_mutex: Arc<Mutex<Bot>>, reactor: Reactor, f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let bot_clone = Arc::clone(&bot_mutex);
+    let handle = spawn(move || {
+        isolate(&bot_clone, &reactor, f);
+    });
+    track(&bot_mutex, handle);
+}
+
+// Lets the manager wait for the thread before starting the bot again
+fn track(bot_mutex: &Arc<Mutex<Bot>>, handle: JoinHandle<()>) {
+    let mut bot = bot_mutex.lock().unwrap();
+    bot.threads.retain(|handle| !handle.is_finished());
+    bot.threads.push(handle);
+}
+
+pub fn record_packet(bot_mutex: &Arc<Mutex<Bot>>, data: &[u8]) {
+    let mut bot = bot_mutex.lock().unwrap();
+    if bot.recent_packets.len() == RECENT_PACKETS {
+        bot.recent_packets.pop_front();
+    }
+    bot.recent_packets.push_back(data.to_vec());
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
@@ -1,156 +1,385 @@
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
 
-use enet::{Peer, PeerID};
-use spdlog::{info, warn};
+use spdlog::{debug, info, warn};
 
-use crate::bot::{disconnect, find_path, place, punch, send_packet, talk, walk};
+use crate::bot::command::Command;
+use crate::bot::{disconnect, send_packet};
+use crate::types::bot_state::BotState;
+use crate::types::call_function::CallFunction;
 use crate::types::e_packet_type::EPacketType;
+use crate::types::packet_error::PacketError;
+use crate::types::player::Player;
 use crate::types::tank_packet_type::TankPacketType;
-use crate::utils::text_parse;
+use crate::types::text_packet::TextPacket;
 use crate::utils::variant::VariantList;
 
 use super::Bot;
 
-pub fn handle(bot_mutex: &Arc<Mutex<Bot>>, pkt: &TankPacketType, data: &[u8]) {
-    let mut bot = bot_mutex.lock().unwrap();
-    let variant = VariantList::deserialize(&data).unwrap();
-    let function_call: String = variant.get(0).unwrap().as_string();
-    info!("Received function call: {}", function_call);
-
-    match function_call.as_str() {
-        "OnSendToServer" => {
-            let port = variant.get(1).unwrap().as_int32();
-            let token = variant.get(2).unwrap().as_int32();
-            let user_id = variant.get(3).unwrap().as_int32();
-            let server_data = variant.get(4).unwrap().as_string();
-            let parsed_server_data = text_parse::parse_and_store_as_vec(&server_data);
-
-            // if bot.display_name.is_empty() {
-            //     bot.username = variant.get(6).unwrap().as_string();
-            //     error!("Username: {}", bot.username);
-            // }
-
-            bot.state.is_redirect = true;
-            bot.server.ip = parsed_server_data.get(0).unwrap().to_string();
-            bot.server.port = port.to_string();
-            bot.info.login_info.token = token.to_string();
-            bot.info.login_info.user = user_id.to_string();
-            bot.info.login_info.door_id = parsed_server_data.get(1).unwrap().to_string();
-            bot.info.login_info.uuid = parsed_server_data.get(2).unwrap().to_string();
-            let peer_id = bot.peer_id.unwrap();
-            disconnect(peer_id);
-        }
-        "OnSuperMainStartAcceptLogonHrdxs47254722215a" => {
-            let peer_id = bot.peer_id.unwrap();
-            send_packet(
-                peer_id,
-                EPacketType::NetMessageGenericText,
-                "action|enter_game\n".to_string(),
-            );
-            bot.state.is_redirect = false;
-        }
-        "OnCountryState" => {
-            // I'm not sure why this is sent twice, but it is.
-            let peer_id = bot.peer_id.unwrap();
-            send_packet(
-                peer_id,
-                EPacketType::NetMessageGenericText,
-                "action|getDRAnimations\n".to_string(),
-            );
-            send_packet(
-                peer_id,
-                EPacketType::NetMessageGenericText,
-                "action|getDRAnimations\n".to_string(),
-            );
-        }
-        "OnDialogRequest" => {
-            let message = variant.get(1).unwrap().as_string();
-            if message.contains("Gazette") {
-                let peer_id = bot.peer_id.unwrap();
-                send_packet(
-                    peer_id,
-                    EPacketType::NetMessageGenericText,
-                    "action|dialog_return\ndialog_name|gazette\nbuttonClicked|banner\n".to_string(),
-                );
-            }
-        }
-        "OnSetBux" => {
-            let bux = variant.get(1).unwrap().as_int32();
-            bot.state.gems = bux;
-        }
-        "OnConsoleMessage" => {
-            let message = variant.get(1).unwrap().as_string();
-            info!("Received console message: {}", message);
-        }
-        "OnSetPos" => {
-            let pos = variant.get(1).unwrap().as_vec2();
-            info!("Received position: {:?}", pos);
-            bot.position.x = pos.0;
-            bot.position.y = pos.1;
-            if bot.state.is_ingame {
-                let peer_id = bot.peer_id.unwrap();
-                place(&bot_mutex, peer_id, 0, -1, 9640);
-            }
-        }
-        "ShowStartFTUEPopup" => {
-            return;
-        }
-        "OnFtueButtonDataSet" => {
-            let unknown_1 = variant.get(1).unwrap().as_int32();
-            let current_progress = variant.get(2).unwrap().as_int32();
-            let total_progress = variant.get(3).unwrap().as_int32();
-            let info = variant.get(4).unwrap().as_string();
-            info!(
-                "Received FTUE button data set: {} {} {} {}",
-                unknown_1, current_progress, total_progress, info
-            );
-        }
-        "OnHideMenusRequest" => {
-            warn!("Received OnHideMenusRequest");
-        }
-        "OnSpawn" => {
-            let message = variant.get(1).unwrap().as_string();
-            let data = text_parse::parse_and_store_as_map(&message);
-            bot.state.is_ingame = true;
-            bot.state.net_id = data.get("netID").unwrap().parse().unwrap();
+// Runs with the bot locked. It's only ever called with the function it's registered under.
+type Handler = fn(&mut Bot, &TankPacketType, CallFunction) -> Result<(), PacketError>;
+
+// Supporting a new call function means parsing it in CallFunction and registering a handler
+// here under its CallFunction::name()
+static HANDLERS: LazyLock<HashMap<&'static str, Handler>> = LazyLock::new(|| {
+    let mut handlers: HashMap<&'static str, Handler> = HashMap::new();
+    handlers.insert("OnSendToServer", on_send_to_server);
+    handlers.insert(
+        "OnSuperMainStartAcceptLogonHrdxs47254722215a",
+        on_super_main_start_accept_logon,
+    );
+    handlers.insert("OnCountryState", on_country_state);
+    handlers.insert("OnDialogRequest", on_dialog_request);
+    handlers.insert("OnSetBux", on_set_bux);
+    handlers.insert("OnConsoleMessage", on_console_message);
+    handlers.insert("OnSetPos", on_set_pos);
+    handlers.insert("ShowStartFTUEPopup", ignore);
+    handlers.insert("OnFtueButtonDataSet", on_ftue_button_data_set);
+    handlers.insert("OnHideMenusRequest", on_hide_menus_request);
+    handlers.insert("OnSpawn", on_spawn);
+    handlers.insert("OnRemove", on_remove);
+    handlers.insert("OnTalkBubble", on_talk_bubble);
+    handlers.insert("OnTextOverlay", on_text_overlay);
+    handlers.insert("OnClearTutorialArrow", on_clear_tutorial_arrow);
+    handlers.insert("OnSetClothing", on_set_clothing);
+    handlers.insert("OnRequestWorldSelectMenu", on_request_world_select_menu);
+    handlers
+});
+
+pub fn handle(
+    bot_mutex: &Arc<Mutex<Bot>>,
+    pkt: &TankPacketType,
+    data: &[u8],
+) -> Result<(), PacketError> {
+    let function = CallFunction::parse(VariantList::deserialize(data)?)?;
+    info!("Received function call: {}", function.name());
+
+    match HANDLERS.get(function.name()) {
+        Some(handler) => {
+            let mut bot = bot_mutex.lock().unwrap();
+            handler(&mut bot, pkt, function)
         }
-        "OnTalkBubble" => {
-            let message = variant.get(2).unwrap().as_string();
-            print!("Received talk bubble: {}", message);
-            if message.contains("mate right") {
-                let peer_id = bot.peer_id.unwrap();
-                walk(&bot_mutex, peer_id, 1.0, 0.0, false);
-            }
-            if message.contains("mate left") {
-                let peer_id = bot.peer_id.unwrap();
-                walk(&bot_mutex, peer_id, -1.0, 0.0, false);
-            }
-            if message.contains("mate up") {
-                let peer_id = bot.peer_id.unwrap();
-                walk(&bot_mutex, peer_id, 0.0, -1.0, false);
-            }
-            if message.contains("mate down") {
-                let peer_id = bot.peer_id.unwrap();
-                walk(&bot_mutex, peer_id, 0.0, 1.0, false);
-            }
-            if message.contains("mate say") {
-                let peer_id = bot.peer_id.unwrap();
-                talk(peer_id, "Hello, world!");
-            }
-            if message.contains("mate punch") {
-                let peer_id = bot.peer_id.unwrap();
-                punch(&bot_mutex, peer_id, 0, 1);
-            }
-            if message.contains("mate findp") {
-                let peer_id = bot.peer_id.unwrap();
-                find_path(&bot_mutex, peer_id, 30, 5);
-            }
+        None => {
+            info!("Unhandled function call: {:?}", function);
+            Ok(())
         }
-        "OnClearTutorialArrow" => {
-            let v1 = variant.get(1).unwrap().as_string();
+    }
+}
 
-            println!("Received OnClearTutorialArrow: {} ", v1);
-        }
-        _ => {}
+// A handler was registered under the name of a different function
+fn misregistered(handler: &str) -> PacketError {
+    PacketError::Malformed(format!("{} was given another call function", handler))
+}
+
+fn ignore(_: &mut Bot, _: &TankPacketType, _: CallFunction) -> Result<(), PacketError> {
+    Ok(())
+}
+
+fn on_send_to_server(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnSendToServer {
+        port,
+        token,
+        user_id,
+        ip,
+        door_id,
+        uuid,
+    } = function
+    else {
+        return Err(misregistered("on_send_to_server"));
+    };
+
+    // if bot.display_name.is_empty() {
+    //     bot.username = variant.get(6).unwrap().as_string();
+    //     error!("Username: {}", bot.username);
+    // }
+
+    bot.state.transition(BotState::Redirecting);
+    bot.server.ip = ip;
+    bot.server.port = port.to_string();
+    bot.info.login_info.token = token.to_string();
+    bot.info.login_info.user = user_id.to_string();
+    bot.info.login_info.door_id = door_id;
+    bot.info.login_info.uuid = uuid;
+    let Some(peer_id) = bot.peer_id else {
+        return Ok(());
+    };
+    disconnect(peer_id);
+    Ok(())
+}
+
+fn on_super_main_start_accept_logon(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    _: CallFunction,
+) -> Result<(), PacketError> {
+    bot.state.transition(BotState::InGame);
+    let Some(peer_id) = bot.peer_id else {
+        return Ok(());
+    };
+    send_packet(
+        peer_id,
+        EPacketType::NetMessageGenericText,
+        TextPacket::action("enter_game").serialize(),
+    );
+    Ok(())
+}
+
+fn on_country_state(bot: &mut Bot, _: &TankPacketType, _: CallFunction) -> Result<(), PacketError> {
+    let Some(peer_id) = bot.peer_id else {
+        return Ok(());
+    };
+    // I'm not sure why this is sent twice, but it is.
+    send_packet(
+        peer_id,
+        EPacketType::NetMessageGenericText,
+        TextPacket::action("getDRAnimations").serialize(),
+    );
+    send_packet(
+        peer_id,
+        EPacketType::NetMessageGenericText,
+        TextPacket::action("getDRAnimations").serialize(),
+    );
+    Ok(())
+}
+
+fn on_dialog_request(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnDialogRequest(message) = function else {
+        return Err(misregistered("on_dialog_request"));
+    };
+    let Some(peer_id) = bot.peer_id else {
+        return Ok(());
+    };
+    if let Some(pending) = bot.item_actions.answer(&message) {
+        send_packet(
+            peer_id,
+            EPacketType::NetMessageGenericText,
+            TextPacket::action("dialog_return")
+                .with("dialog_name", pending.action.dialog_name())
+                .with("itemID", format!("{}|", pending.item_id))
+                .with("count", pending.amount)
+                .serialize(),
+        );
+        return Ok(());
+    }
+    if message.contains("Gazette") {
+        send_packet(
+            peer_id,
+            EPacketType::NetMessageGenericText,
+            TextPacket::action("dialog_return")
+                .with("dialog_name", "gazette")
+                .with("buttonClicked", "banner")
+                .serialize(),
+        );
+    }
+    Ok(())
+}
+
+fn on_set_bux(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnSetBux(bux) = function else {
+        return Err(misregistered("on_set_bux"));
+    };
+    bot.state.gems = bux;
+    Ok(())
+}
+
+fn on_console_message(
+    _: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnConsoleMessage(message) = function else {
+        return Err(misregistered("on_console_message"));
+    };
+    info!("Received console message: {}", message);
+    Ok(())
+}
+
+fn on_set_pos(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnSetPos { x, y } = function else {
+        return Err(misregistered("on_set_pos"));
+    };
+    info!("Received position: {:?}", (x, y));
+    bot.position.x = x;
+    bot.position.y = y;
+    if bot.state.current() == BotState::InWorld {
+        bot.command_queue.push_back(Command::Place {
+            offset_x: 0,
+            offset_y: -1,
+            block_id: 9640,
+        });
+    }
+    Ok(())
+}
+
+fn on_ftue_button_data_set(
+    _: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnFtueButtonDataSet {
+        unknown_1,
+        current_progress,
+        total_progress,
+        info,
+    } = function
+    else {
+        return Err(misregistered("on_ftue_button_data_set"));
+    };
+    info!(
+        "Received FTUE button data set: {} {} {} {}",
+        unknown_1, current_progress, total_progress, info
+    );
+    Ok(())
+}
+
+fn on_hide_menus_request(
+    _: &mut Bot,
+    _: &TankPacketType,
+    _: CallFunction,
+) -> Result<(), PacketError> {
+    warn!("Received OnHideMenusRequest");
+    Ok(())
+}
+
+fn on_spawn(bot: &mut Bot, _: &TankPacketType, function: CallFunction) -> Result<(), PacketError> {
+    let CallFunction::OnSpawn { net_id, data } = function else {
+        return Err(misregistered("on_spawn"));
+    };
+    // Only the bot's own avatar is marked local
+    if data.get("type").is_some_and(|kind| kind == "local") {
+        bot.state.transition(BotState::InWorld);
+        bot.state.net_id = net_id;
+    } else {
+        let player = Player::from_spawn(net_id, &data);
+        info!("Player {} ({}) joined the world", player.name, net_id);
+        bot.players.insert(net_id, player);
+    }
+    Ok(())
+}
+
+fn on_remove(bot: &mut Bot, _: &TankPacketType, function: CallFunction) -> Result<(), PacketError> {
+    let CallFunction::OnRemove { net_id } = function else {
+        return Err(misregistered("on_remove"));
+    };
+    if let Some(player) = bot.players.remove(&net_id) {
+        info!("Player {} ({}) left the world", player.name, net_id);
+    }
+    Ok(())
+}
+
+fn on_talk_bubble(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnTalkBubble(message) = function else {
+        return Err(misregistered("on_talk_bubble"));
+    };
+    debug!("Received talk bubble: {}", message);
+    if message.contains("mate right") {
+        bot.command_queue.push_back(Command::Walk {
+            x: 1.0,
+            y: 0.0,
+            ap: false,
+        });
+    }
+    if message.contains("mate left") {
+        bot.command_queue.push_back(Command::Walk {
+            x: -1.0,
+            y: 0.0,
+            ap: false,
+        });
+    }
+    if message.contains("mate up") {
+        bot.command_queue.push_back(Command::Walk {
+            x: 0.0,
+            y: -1.0,
+            ap: false,
+        });
+    }
+    if message.contains("mate down") {
+        bot.command_queue.push_back(Command::Walk {
+            x: 0.0,
+            y: 1.0,
+            ap: false,
+        });
+    }
+    if message.contains("mate say") {
+        bot.command_queue
+            .push_back(Command::Talk("Hello, world!".to_string()));
+    }
+    if message.contains("mate punch") {
+        bot.command_queue.push_back(Command::Punch {
+            offset_x: 0,
+            offset_y: 1,
+        });
+    }
+    if message.contains("mate findp") {
+        bot.command_queue
+            .push_back(Command::FindPath { x: 30, y: 5 });
+    }
+    Ok(())
+}
+
+fn on_text_overlay(
+    _: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnTextOverlay(message) = function else {
+        return Err(misregistered("on_text_overlay"));
+    };
+    info!("Received text overlay: {}", message);
+    Ok(())
+}
+
+fn on_clear_tutorial_arrow(
+    _: &mut Bot,
+    _: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    let CallFunction::OnClearTutorialArrow(v1) = function else {
+        return Err(misregistered("on_clear_tutorial_arrow"));
+    };
+    debug!("Received OnClearTutorialArrow: {}", v1);
+    Ok(())
+}
+
+fn on_set_clothing(
+    _: &mut Bot,
+    pkt: &TankPacketType,
+    function: CallFunction,
+) -> Result<(), PacketError> {
+    info!("Player {} changed clothes: {:?}", pkt.net_id, function);
+    Ok(())
+}
+
+fn on_request_world_select_menu(
+    bot: &mut Bot,
+    _: &TankPacketType,
+    _: CallFunction,
+) -> Result<(), PacketError> {
+    // The world select menu is only shown outside of worlds
+    bot.players.clear();
+    bot.objects.clear();
+    if bot.state.current() == BotState::InWorld {
+        bot.state.transition(BotState::InGame);
     }
+    Ok(())
 }
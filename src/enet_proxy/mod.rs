@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+use enet::*;
+use spdlog::{info, warn};
+
+use crate::bot::packet_handler::{parse_message, summarize};
+use crate::types::call_function::CallFunction;
+use crate::types::e_packet_type::EPacketType;
+use crate::types::e_tank_packet_type::ETankPacketType;
+use crate::types::tank_packet_type::TankPacketType;
+use crate::utils::variant::{Variant, VariantList};
+
+const MAX_CLIENTS: usize = 16;
+// Both hosts are serviced from one thread, so neither may block for long
+const SERVICE_TIMEOUT: Duration = Duration::from_millis(5);
+
+// Sits between a real game client and a server, logging everything that passes through.
+// The client has to be pointed at the listen port (e.g. through its server data), the first
+// connection goes to the given server and redirects are rewritten to come back through here.
+// Run with `Mori --proxy <server ip:port> [listen port]`.
+pub fn run(server: &str, listen_port: u16) -> Result<(), String> {
+    let server = server
+        .parse::<SocketAddrV4>()
+        .map_err(|err| format!("Invalid server address {}: {}", server, err))?;
+    let enet = Enet::new().map_err(|err| format!("Failed to initialize ENet: {:?}", err))?;
+    let listener = create_host(
+        &enet,
+        Some(SocketAddrV4::new(Ipv4Addr::LOCALHOST, listen_port)),
+    )?;
+    let upstream = create_host(&enet, None)?;
+    info!(
+        "Proxying 127.0.0.1:{} to {}, waiting for a client",
+        listen_port, server
+    );
+
+    let mut proxy = Proxy {
+        listener,
+        upstream,
+        listen_port,
+        server,
+        redirect: None,
+        sessions: HashMap::new(),
+        clients: HashMap::new(),
+    };
+    loop {
+        proxy.poll_listener()?;
+        proxy.poll_upstream()?;
+    }
+}
+
+fn create_host(enet: &Enet, address: Option<SocketAddrV4>) -> Result<Host<()>, String> {
+    let address = address.map(|address| Address::new(*address.ip(), address.port()));
+    enet.create_host::<()>(
+        address.as_ref(),
+        MAX_CLIENTS,
+        ChannelLimit::Limited(2),
+        BandwidthLimit::Unlimited,
+        BandwidthLimit::Unlimited,
+        true,
+        false,
+    )
+    .map_err(|err| format!("Failed to create ENet host: {}", err))
+}
+
+// A client and the server connection made for it
+struct Session {
+    server: PeerID,
+    connected: bool,
+    // What the client sent before the server connection was up
+    pending: Vec<(u8, Vec<u8>)>,
+}
+
+struct Proxy {
+    listener: Host<()>,
+    upstream: Host<()>,
+    listen_port: u16,
+    server: SocketAddrV4,
+    // Where the client was redirected to, its next connection goes there
+    redirect: Option<SocketAddrV4>,
+    // Keyed by client peer
+    sessions: HashMap<PeerID, Session>,
+    // Server peer to client peer
+    clients: HashMap<PeerID, PeerID>,
+}
+
+impl Proxy {
+    fn poll_listener(&mut self) -> Result<(), String> {
+        let event = self
+            .listener
+            .service(SERVICE_TIMEOUT)
+            .map_err(|err| format!("Service failed: {}", err))?;
+        let event = match event {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+        let client = event.peer_id();
+        match event.take_kind() {
+            EventKind::Connect => {
+                let target = self.redirect.take().unwrap_or(self.server);
+                info!("Client connected, connecting to {}", target);
+                match self
+                    .upstream
+                    .connect(&Address::new(*target.ip(), target.port()), 2, 0)
+                {
+                    Ok(server) => {
+                        self.clients.insert(server, client);
+                        self.sessions.insert(
+                            client,
+                            Session {
+                                server,
+                                connected: false,
+                                pending: Vec::new(),
+                            },
+                        );
+                    }
+                    Err(err) => {
+                        warn!("Failed to connect to {}: {}", target, err);
+                        disconnect(&mut self.listener, client);
+                    }
+                }
+            }
+            EventKind::Disconnect { .. } => {
+                info!("Client disconnected");
+                if let Some(session) = self.sessions.remove(&client) {
+                    self.clients.remove(&session.server);
+                    disconnect(&mut self.upstream, session.server);
+                }
+            }
+            EventKind::Receive { channel_id, packet } => {
+                let data = packet.data().to_vec();
+                info!("client -> server: {}", summarize(&data));
+                if let Some(session) = self.sessions.get_mut(&client) {
+                    if session.connected {
+                        forward(&mut self.upstream, session.server, channel_id, data);
+                    } else {
+                        session.pending.push((channel_id, data));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_upstream(&mut self) -> Result<(), String> {
+        let event = self
+            .upstream
+            .service(SERVICE_TIMEOUT)
+            .map_err(|err| format!("Service failed: {}", err))?;
+        let event = match event {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+        let server = event.peer_id();
+        let client = match self.clients.get(&server) {
+            Some(client) => *client,
+            None => return Ok(()),
+        };
+        match event.take_kind() {
+            EventKind::Connect => {
+                info!("Connected to the server");
+                if let Some(session) = self.sessions.get_mut(&client) {
+                    session.connected = true;
+                    for (channel_id, data) in session.pending.drain(..) {
+                        forward(&mut self.upstream, server, channel_id, data);
+                    }
+                }
+            }
+            EventKind::Disconnect { .. } => {
+                info!("Server disconnected");
+                self.clients.remove(&server);
+                self.sessions.remove(&client);
+                disconnect(&mut self.listener, client);
+            }
+            EventKind::Receive { channel_id, packet } => {
+                let mut data = packet.data().to_vec();
+                info!("server -> client: {}", summarize(&data));
+                if let Some((rewritten, target)) = rewrite_redirect(&data, self.listen_port) {
+                    info!("Redirect to {} rewritten to come through the proxy", target);
+                    self.redirect = Some(target);
+                    data = rewritten;
+                }
+                forward(&mut self.listener, client, channel_id, data);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Points an OnSendToServer at the proxy, returns the packet and where it was meant to go
+fn rewrite_redirect(data: &[u8], listen_port: u16) -> Option<(Vec<u8>, SocketAddrV4)> {
+    let (packet_type, payload) = parse_message(data).ok()?;
+    if packet_type != EPacketType::NetMessageGamePacket {
+        return None;
+    }
+    let mut pkt = TankPacketType::deserialize(payload).ok()?;
+    if pkt.packet_type != ETankPacketType::NetGamePacketCallFunction {
+        return None;
+    }
+    let variants = VariantList::deserialize(&pkt.extended_data).ok()?;
+    let CallFunction::OnSendToServer {
+        port,
+        token,
+        user_id,
+        ip,
+        door_id,
+        uuid,
+    } = CallFunction::parse(variants.clone()).ok()?
+    else {
+        return None;
+    };
+    let target = SocketAddrV4::new(ip.parse().ok()?, u16::try_from(port).ok()?);
+
+    let mut rewritten = vec![
+        Variant::String("OnSendToServer".to_string()),
+        Variant::Signed(listen_port as i32),
+        Variant::Signed(token),
+        Variant::Signed(user_id),
+        Variant::String(format!("{}|{}|{}", Ipv4Addr::LOCALHOST, door_id, uuid)),
+    ];
+    // Whatever follows the server data (e.g. the login mode) isn't parsed, pass it on as is
+    rewritten.extend(variants.iter().skip(rewritten.len()).cloned());
+    pkt.extended_data = VariantList::from(rewritten).serialize().ok()?;
+
+    let mut data = (EPacketType::NetMessageGamePacket as u32)
+        .to_le_bytes()
+        .to_vec();
+    data.extend_from_slice(&pkt.serialize());
+    Some((data, target))
+}
+
+fn forward(host: &mut Host<()>, peer_id: PeerID, channel_id: u8, data: Vec<u8>) {
+    let packet = match Packet::new(data, PacketMode::ReliableSequenced) {
+        Ok(packet) => packet,
+        Err(err) => {
+            warn!("Failed to create a packet: {}", err);
+            return;
+        }
+    };
+    if let Some(peer) = host.peer_mut(peer_id) {
+        if let Err(err) = peer.send_packet(packet, channel_id) {
+            warn!("Failed to forward a packet: {}", err);
+        }
+    }
+}
+
+fn disconnect(host: &mut Host<()>, peer_id: PeerID) {
+    if let Some(peer) = host.peer_mut(peer_id) {
+        peer.disconnect(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on_send_to_server(port: i32, server_data: &str) -> Vec<u8> {
+        let mut pkt = TankPacketType::new();
+        pkt.packet_type = ETankPacketType::NetGamePacketCallFunction;
+        pkt.net_id = u32::MAX;
+        pkt.extended_data = VariantList::from(vec![
+            Variant::String("OnSendToServer".to_string()),
+            Variant::Signed(port),
+            Variant::Signed(1234),
+            Variant::Signed(5678),
+            Variant::String(server_data.to_string()),
+            Variant::Signed(1),
+        ])
+        .serialize()
+        .unwrap();
+        let mut data = (EPacketType::NetMessageGamePacket as u32)
+            .to_le_bytes()
+            .to_vec();
+        data.extend_from_slice(&pkt.serialize());
+        data
+    }
+
+    fn call_function(data: &[u8]) -> (CallFunction, VariantList) {
+        let (_, payload) = parse_message(data).unwrap();
+        let pkt = TankPacketType::deserialize(payload).unwrap();
+        let variants = VariantList::deserialize(&pkt.extended_data).unwrap();
+        (CallFunction::parse(variants.clone()).unwrap(), variants)
+    }
+
+    #[test]
+    fn points_the_redirect_at_the_proxy() {
+        let data = on_send_to_server(17198, "213.179.209.168|door|uuid-1");
+
+        let (rewritten, target) = rewrite_redirect(&data, 17091).unwrap();
+
+        assert_eq!(target, "213.179.209.168:17198".parse().unwrap());
+        let (function, variants) = call_function(&rewritten);
+        assert_eq!(
+            function,
+            CallFunction::OnSendToServer {
+                port: 17091,
+                token: 1234,
+                user_id: 5678,
+                ip: "127.0.0.1".to_string(),
+                door_id: "door".to_string(),
+                uuid: "uuid-1".to_string(),
+            }
+        );
+        assert_eq!(variants.get(5), Some(&Variant::Signed(1)));
+    }
+
+    #[test]
+    fn leaves_other_packets_alone() {
+        assert!(
+            rewrite_redirect(&on_send_to_server(17198, "not an ip|door|uuid"), 17091).is_none()
+        );
+        assert!(rewrite_redirect(&on_send_to_server(-1, "127.0.0.1|door|uuid"), 17091).is_none());
+
+        let mut pkt = TankPacketType::new();
+        pkt.packet_type = ETankPacketType::NetGamePacketCallFunction;
+        pkt.extended_data = VariantList::from(vec![
+            Variant::String("OnSetBux".to_string()),
+            Variant::Signed(5),
+        ])
+        .serialize()
+        .unwrap();
+        let mut data = (EPacketType::NetMessageGamePacket as u32)
+            .to_le_bytes()
+            .to_vec();
+        data.extend_from_slice(&pkt.serialize());
+        assert!(rewrite_redirect(&data, 17091).is_none());
+    }
+}
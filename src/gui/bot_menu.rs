@@ -1,15 +1,45 @@
+use std::fs;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+
 use eframe::egui::{self, Ui};
+use spdlog::error;
 
-use crate::{bot::warp, Bot};
+use crate::{
+    bot::{command::Command, item_actions::ItemActionResult},
+    manager::Manager,
+    types::bot_state::BotState,
+    Bot, Data,
+};
 
-#[derive(Default)]
 pub struct BotMenu {
     pub selected_bot: String,
     pub warp_name: String,
+    pub collect_radius: f32,
+    pub item_id: u16,
+    pub item_amount: u8,
+    // The drop or trash waiting for the server, polled every frame
+    pub item_action: Option<Receiver<ItemActionResult>>,
+    pub item_action_status: String,
+}
+
+impl Default for BotMenu {
+    fn default() -> Self {
+        BotMenu {
+            selected_bot: String::new(),
+            warp_name: String::new(),
+            collect_radius: 5.0,
+            item_id: 0,
+            item_amount: 1,
+            item_action: None,
+            item_action_status: String::new(),
+        }
+    }
 }
 
 impl BotMenu {
-    pub fn render(&mut self, ui: &mut Ui, bots: &Vec<Bot>, manager: &crate::Manager) {
+    pub fn render(&mut self, ui: &mut Ui, bots: &mut Vec<Bot>, manager: &mut Manager) {
+        self.poll_item_action(ui);
         ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
                 egui::Grid::new("bots_grid")
@@ -18,7 +48,7 @@ impl BotMenu {
                     .show(ui, |ui| {
                         ui.label("Bots");
                         ui.end_row();
-                        for bot in bots {
+                        for bot in bots.iter() {
                             if ui
                                 .add(egui::Button::new(bot.username.clone()).truncate())
                                 .clicked()
@@ -45,12 +75,28 @@ impl BotMenu {
                                     .max_col_width(120.0)
                                     .show(ui, |ui| {
                                         if let Some(bot) = manager.get_bot(&self.selected_bot) {
-                                            let (status, ping, world_name) = {
+                                            let (status, ping, world_name, inventory) = {
                                                 let bot_mutex = bot.lock().unwrap();
+                                                let inventory = &bot_mutex.inventory;
                                                 (
-                                                    bot_mutex.info.status.clone(),
+                                                    format!(
+                                                        "{} ({}s)",
+                                                        bot_mutex.state.current(),
+                                                        bot_mutex.state.elapsed().as_secs()
+                                                    ),
                                                     bot_mutex.info.ping.clone().to_string(),
                                                     bot_mutex.world.name.clone(),
+                                                    if inventory.size == 0 {
+                                                        "Not received".to_string()
+                                                    } else if inventory.is_full() {
+                                                        format!("Full ({} slots)", inventory.size)
+                                                    } else {
+                                                        format!(
+                                                            "{} of {} slots free",
+                                                            inventory.free_slots(),
+                                                            inventory.size
+                                                        )
+                                                    },
                                                 )
                                             };
                                             ui.label("Status");
@@ -62,6 +108,9 @@ impl BotMenu {
                                             ui.label("World");
                                             ui.label(world_name);
                                             ui.end_row();
+                                            ui.label("Inventory");
+                                            ui.label(inventory);
+                                            ui.end_row();
                                         } else {
                                             ui.label("Status");
                                             ui.label("EMPTY");
@@ -72,6 +121,9 @@ impl BotMenu {
                                             ui.label("World");
                                             ui.label("EXIT");
                                             ui.end_row();
+                                            ui.label("Inventory");
+                                            ui.label("EMPTY");
+                                            ui.end_row();
                                         }
                                     });
                             });
@@ -90,14 +142,128 @@ impl BotMenu {
                             ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
                                 if ui.button("Warp").clicked() {
                                     if let Some(bot) = manager.get_bot(&self.selected_bot) {
-                                        let bot_mutex = bot.lock().unwrap();
-                                        let peer_id = bot_mutex.peer_id.unwrap().clone();
-                                        warp(peer_id, &self.warp_name);
+                                        let mut bot_mutex = bot.lock().unwrap();
+                                        bot_mutex
+                                            .command_queue
+                                            .push_back(Command::Warp(self.warp_name.clone()));
                                     }
                                 }
                             });
                         });
                         ui.allocate_space(egui::vec2(half_width, 5.0));
+                        ui.group(|ui| {
+                            ui.label("Control");
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                let paused = match manager.get_bot(&self.selected_bot) {
+                                    Some(bot) => bot.lock().unwrap().state.paused,
+                                    None => false,
+                                };
+                                if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                                    manager.set_bot_paused(&self.selected_bot, !paused);
+                                }
+                                if ui.button("Stop").clicked() {
+                                    manager.stop_bot(&self.selected_bot);
+                                }
+                                if ui.button("Restart").clicked() {
+                                    manager.restart_bot(&self.selected_bot);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    manager.remove_bot(&self.selected_bot);
+                                    bots.retain(|bot| bot.username != self.selected_bot);
+                                    let mut data = serde_json::from_str::<Data>(
+                                        &fs::read_to_string("data.json").unwrap(),
+                                    )
+                                    .unwrap();
+                                    data.bots.retain(|bot| bot.username != self.selected_bot);
+                                    fs::write(
+                                        "data.json",
+                                        serde_json::to_string_pretty(&data).unwrap(),
+                                    )
+                                    .unwrap();
+                                    self.selected_bot.clear();
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                let capture = manager.get_bot(&self.selected_bot).and_then(|bot| {
+                                    bot.lock()
+                                        .unwrap()
+                                        .capture
+                                        .as_ref()
+                                        .map(|recorder| recorder.path().to_string())
+                                });
+                                match capture {
+                                    Some(path) => {
+                                        if ui.button("Stop capture").clicked() {
+                                            manager.stop_capture(&self.selected_bot);
+                                        }
+                                        ui.label(path);
+                                    }
+                                    None => {
+                                        if ui.button("Start capture").clicked() {
+                                            if let Err(err) =
+                                                manager.start_capture(&self.selected_bot)
+                                            {
+                                                error!("{}", err);
+                                            }
+                                        }
+                                    }
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Collect within");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.collect_radius)
+                                        .range(0.0..=100.0)
+                                        .suffix(" tiles"),
+                                );
+                                if ui.button("Collect").clicked() {
+                                    if let Some(bot) = manager.get_bot(&self.selected_bot) {
+                                        bot.lock().unwrap().command_queue.push_back(
+                                            Command::CollectAll {
+                                                radius: self.collect_radius,
+                                            },
+                                        );
+                                    }
+                                }
+                                if let Some(bot) = manager.get_bot(&self.selected_bot) {
+                                    let mut bot_mutex = bot.lock().unwrap();
+                                    ui.label("Pickup range");
+                                    ui.add(
+                                        egui::DragValue::new(&mut bot_mutex.state.collect_range)
+                                            .range(0.5..=5.0)
+                                            .speed(0.1)
+                                            .suffix(" tiles"),
+                                    );
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Item");
+                                ui.add(egui::DragValue::new(&mut self.item_id));
+                                ui.add(
+                                    egui::DragValue::new(&mut self.item_amount)
+                                        .range(1..=200)
+                                        .prefix("x"),
+                                );
+                                let idle = self.item_action.is_none();
+                                if ui.add_enabled(idle, egui::Button::new("Drop")).clicked() {
+                                    self.start_item_action(manager.drop_item(
+                                        &self.selected_bot,
+                                        self.item_id,
+                                        self.item_amount,
+                                    ));
+                                }
+                                if ui.add_enabled(idle, egui::Button::new("Trash")).clicked() {
+                                    self.start_item_action(manager.trash_item(
+                                        &self.selected_bot,
+                                        self.item_id,
+                                        self.item_amount,
+                                    ));
+                                }
+                                ui.add(egui::Label::new(&self.item_action_status).truncate());
+                            });
+                        });
+                        ui.allocate_space(egui::vec2(half_width, 5.0));
                         ui.group(|ui| {
                             ui.vertical(|ui| {
                                 ui.label("Server");
@@ -130,6 +296,72 @@ impl BotMenu {
                                         }
                                     });
                             });
+                        });
+                        ui.allocate_space(egui::vec2(half_width, 5.0));
+                        ui.group(|ui| {
+                            ui.vertical(|ui| {
+                                // Item name, total count, drops and the nearest one in tiles
+                                let mut drops = Vec::new();
+                                if let Some(bot) = manager.get_bot(&self.selected_bot) {
+                                    let bot_mutex = bot.lock().unwrap();
+                                    let mut item_ids = bot_mutex
+                                        .objects
+                                        .items
+                                        .iter()
+                                        .map(|object| object.item_id)
+                                        .collect::<Vec<_>>();
+                                    item_ids.sort();
+                                    item_ids.dedup();
+                                    for item_id in item_ids {
+                                        let objects = bot_mutex.objects.of_item(item_id);
+                                        let name = bot_mutex
+                                            .world
+                                            .item_database
+                                            .get_item(&(item_id as u32))
+                                            .map_or(item_id.to_string(), |item| item.name.clone());
+                                        let count = objects
+                                            .iter()
+                                            .map(|object| object.count as u32)
+                                            .sum::<u32>();
+                                        let nearest = bot_mutex
+                                            .objects
+                                            .nearest(item_id, &bot_mutex.position)
+                                            .map(|object| {
+                                                format!(
+                                                    "{}, {}",
+                                                    (object.position.x / 32.0) as i32,
+                                                    (object.position.y / 32.0) as i32
+                                                )
+                                            })
+                                            .unwrap_or_default();
+                                        drops.push((name, count, objects.len(), nearest));
+                                    }
+                                }
+                                ui.label(format!("Dropped items ({})", drops.len()));
+                                ui.separator();
+                                egui::ScrollArea::vertical().id_source("bot_drops").show(
+                                    ui,
+                                    |ui| {
+                                        egui::Grid::new("bot_drops_grid").striped(true).show(
+                                            ui,
+                                            |ui| {
+                                                ui.label("Item");
+                                                ui.label("Count");
+                                                ui.label("Drops");
+                                                ui.label("Nearest");
+                                                ui.end_row();
+                                                for (name, count, drop_count, nearest) in drops {
+                                                    ui.add(egui::Label::new(name).truncate());
+                                                    ui.label(count.to_string());
+                                                    ui.label(drop_count.to_string());
+                                                    ui.label(nearest);
+                                                    ui.end_row();
+                                                }
+                                            },
+                                        );
+                                    },
+                                );
+                            });
                             ui.add_space(ui.available_height());
                         });
                     });
@@ -194,13 +426,26 @@ impl BotMenu {
                                     .max_col_width(120.0)
                                     .show(ui, |ui| {
                                         if let Some(bot) = manager.get_bot(&self.selected_bot) {
-                                            let (net_id, token, is_banned, position) = {
+                                            let (
+                                                net_id,
+                                                token,
+                                                is_banned,
+                                                position,
+                                                disconnect_reason,
+                                                reconnect_attempts,
+                                                crash,
+                                                crash_count,
+                                            ) = {
                                                 let bot_mutex = bot.lock().unwrap();
                                                 (
                                                     bot_mutex.state.net_id.clone(),
                                                     bot_mutex.info.token.clone(),
-                                                    bot_mutex.state.is_banned.clone(),
+                                                    bot_mutex.state.current() == BotState::Banned,
                                                     bot_mutex.position.clone(),
+                                                    bot_mutex.state.disconnect_reason,
+                                                    bot_mutex.state.reconnect_attempts,
+                                                    bot_mutex.state.crash.clone(),
+                                                    bot_mutex.state.crash_count,
                                                 )
                                             };
                                             ui.label("NetID");
@@ -219,6 +464,32 @@ impl BotMenu {
                                                 ui.label(position.y.to_string());
                                             });
                                             ui.end_row();
+                                            ui.label("Last disconnect");
+                                            ui.label(match disconnect_reason {
+                                                Some(reason) => reason.to_string(),
+                                                None => "None".to_string(),
+                                            });
+                                            ui.end_row();
+                                            ui.label("Reconnects");
+                                            ui.label(reconnect_attempts.to_string());
+                                            ui.end_row();
+                                            ui.label("Last crash");
+                                            match crash {
+                                                Some(crash) => ui.add(
+                                                    egui::Label::new(format!(
+                                                        "{} while {} ({}s ago)",
+                                                        crash.message,
+                                                        crash.state,
+                                                        crash.crashed_at.elapsed().as_secs()
+                                                    ))
+                                                    .truncate(),
+                                                ),
+                                                None => ui.label("None"),
+                                            };
+                                            ui.end_row();
+                                            ui.label("Crashes");
+                                            ui.label(crash_count.to_string());
+                                            ui.end_row();
                                         } else {
                                             ui.label("NetID");
                                             ui.label("EMPTY");
@@ -236,9 +507,77 @@ impl BotMenu {
                                                 ui.label("0");
                                             });
                                             ui.end_row();
+                                            ui.label("Last disconnect");
+                                            ui.label("EMPTY");
+                                            ui.end_row();
+                                            ui.label("Reconnects");
+                                            ui.label("0");
+                                            ui.end_row();
+                                            ui.label("Last crash");
+                                            ui.label("EMPTY");
+                                            ui.end_row();
+                                            ui.label("Crashes");
+                                            ui.label("0");
+                                            ui.end_row();
                                         }
                                     });
                             });
+                        });
+                        ui.allocate_space(egui::vec2(half_width, 5.0));
+                        ui.group(|ui| {
+                            ui.vertical(|ui| {
+                                let mut players = match manager.get_bot(&self.selected_bot) {
+                                    Some(bot) => bot
+                                        .lock()
+                                        .unwrap()
+                                        .players
+                                        .values()
+                                        .cloned()
+                                        .collect::<Vec<_>>(),
+                                    None => Vec::new(),
+                                };
+                                players.sort_by_key(|player| player.net_id);
+                                ui.label(format!("Players ({})", players.len()));
+                                ui.separator();
+                                egui::ScrollArea::vertical().id_source("bot_players").show(
+                                    ui,
+                                    |ui| {
+                                        egui::Grid::new("bot_players_grid").striped(true).show(
+                                            ui,
+                                            |ui| {
+                                                ui.label("Name");
+                                                ui.label("NetID");
+                                                ui.label("Country");
+                                                ui.label("Position");
+                                                ui.label("Flags");
+                                                ui.end_row();
+                                                for player in players {
+                                                    let mut flags = Vec::new();
+                                                    if player.is_mod {
+                                                        flags.push("mod");
+                                                    }
+                                                    if player.invisible {
+                                                        flags.push("invisible");
+                                                    }
+                                                    ui.add(
+                                                        egui::Label::new(player.name).truncate(),
+                                                    );
+                                                    ui.label(player.net_id.to_string());
+                                                    ui.label(player.country);
+                                                    // In tiles, easier to read than pixels
+                                                    ui.label(format!(
+                                                        "{}, {}",
+                                                        (player.position.x / 32.0) as i32,
+                                                        (player.position.y / 32.0) as i32
+                                                    ));
+                                                    ui.label(flags.join(", "));
+                                                    ui.end_row();
+                                                }
+                                            },
+                                        );
+                                    },
+                                );
+                            });
                             ui.add_space(ui.available_height());
                         });
                     });
@@ -246,4 +585,34 @@ impl BotMenu {
             });
         });
     }
+
+    fn start_item_action(&mut self, receiver: Result<Receiver<ItemActionResult>, String>) {
+        match receiver {
+            Ok(receiver) => {
+                self.item_action = Some(receiver);
+                self.item_action_status = "Waiting for the server".to_string();
+            }
+            Err(err) => self.item_action_status = err,
+        }
+    }
+
+    fn poll_item_action(&mut self, ui: &Ui) {
+        let Some(receiver) = &self.item_action else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(Ok(left)) => self.item_action_status = format!("Done, {} left", left),
+            Ok(Err(err)) => self.item_action_status = err,
+            // The command was dropped, e.g. the bot wasn't connected
+            Err(TryRecvError::Disconnected) => {
+                self.item_action_status = "The bot didn't take the request".to_string()
+            }
+            Err(TryRecvError::Empty) => {
+                // Nothing else repaints while waiting on the server
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+                return;
+            }
+        }
+        self.item_action = None;
+    }
 }
@@ -2,3 +2,4 @@ pub mod add_bot_dialog;
 pub mod bot_menu;
 pub mod item_database;
 pub mod navbar;
+pub mod packet_inspector;
@@ -46,6 +46,15 @@ impl Navbar {
             {
                 self.current_menu = "item_database".to_string();
             }
+            if ui
+                .add(egui::Button::image_and_text(
+                    include_image!("../../assets/packets.png"),
+                    "Packets",
+                ))
+                .clicked()
+            {
+                self.current_menu = "packets".to_string();
+            }
             if ui
                 .add(egui::Button::image_and_text(
                     include_image!("../../assets/blocks.png"),
@@ -0,0 +1,238 @@
+use std::fmt::Write;
+use std::fs;
+use std::sync::Arc;
+
+use eframe::egui::{self, Ui};
+use spdlog::{error, info};
+
+use crate::bot::capture::{Direction, Record};
+use crate::bot::packet_handler::{parse_message, summarize};
+use crate::manager::Manager;
+use crate::types::e_packet_type::EPacketType;
+use crate::types::e_tank_packet_type::ETankPacketType;
+use crate::types::tank_packet_type::TankPacketType;
+use crate::types::text_packet::TextPacket;
+use crate::utils::variant::VariantList;
+
+pub struct PacketInspector {
+    filter: String,
+    show_inbound: bool,
+    show_outbound: bool,
+    selected: Option<u64>,
+}
+
+impl Default for PacketInspector {
+    fn default() -> Self {
+        PacketInspector {
+            filter: String::new(),
+            show_inbound: true,
+            show_outbound: true,
+            selected: None,
+        }
+    }
+}
+
+impl PacketInspector {
+    pub fn render(&mut self, ui: &mut Ui, selected_bot: &str, manager: &mut Manager) {
+        let log = match manager.get_bot(selected_bot) {
+            Some(bot) => Arc::clone(&bot.lock().unwrap().packet_log),
+            None => {
+                ui.label("Select a bot in the Bots menu to see its packets");
+                return;
+            }
+        };
+
+        let filter = self.filter.to_lowercase();
+        let records = log
+            .snapshot()
+            .into_iter()
+            .filter(|(_, record)| match record.direction {
+                Direction::Inbound => self.show_inbound,
+                Direction::Outbound => self.show_outbound,
+            })
+            .map(|(id, record)| {
+                let summary = summarize(&record.data);
+                (id, record, summary)
+            })
+            .filter(|(_, _, summary)| filter.is_empty() || summary.to_lowercase().contains(&filter))
+            .collect::<Vec<_>>();
+
+        ui.horizontal(|ui| {
+            let paused = log.is_paused();
+            if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                log.set_paused(!paused);
+            }
+            if ui.button("Clear").clicked() {
+                log.clear();
+                self.selected = None;
+            }
+            ui.separator();
+            ui.checkbox(&mut self.show_inbound, "Inbound");
+            ui.checkbox(&mut self.show_outbound, "Outbound");
+            ui.label("Filter");
+            ui.text_edit_singleline(&mut self.filter);
+            ui.label(format!("{} packets", records.len()));
+        });
+        ui.separator();
+
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
+            let row_height = ui.text_style_height(&egui::TextStyle::Body);
+            ui.allocate_ui(egui::vec2(360.0, ui.available_height()), |ui| {
+                egui::ScrollArea::vertical()
+                    .id_source("packet_list")
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, records.len(), |ui, row_range| {
+                        ui.set_width(360.0);
+                        ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Truncate);
+                        for (id, record, summary) in &records[row_range] {
+                            let arrow = match record.direction {
+                                Direction::Inbound => "<-",
+                                Direction::Outbound => "->",
+                            };
+                            let text = format!(
+                                "{:>8.3}s {} {}",
+                                record.elapsed.as_secs_f32(),
+                                arrow,
+                                summary
+                            );
+                            if ui
+                                .selectable_label(self.selected == Some(*id), text)
+                                .clicked()
+                            {
+                                self.selected = Some(*id);
+                            }
+                        }
+                    });
+            });
+            ui.separator();
+
+            let record = records
+                .iter()
+                .find(|(id, _, _)| self.selected == Some(*id))
+                .map(|(_, record, _)| record);
+            ui.vertical(|ui| match record {
+                Some(record) => self.render_details(ui, selected_bot, record),
+                None => {
+                    ui.label("Select a packet to decode it");
+                }
+            });
+        });
+    }
+
+    fn render_details(&self, ui: &mut Ui, selected_bot: &str, record: &Record) {
+        let text = describe(record);
+        ui.horizontal(|ui| {
+            if ui.button("Copy").clicked() {
+                ui.output_mut(|output| output.copied_text = text.clone());
+            }
+            if ui.button("Export").clicked() {
+                let path = format!(
+                    "captures/{}-packet-{}.txt",
+                    selected_bot,
+                    record.elapsed.as_millis()
+                );
+                match fs::create_dir_all("captures").and_then(|_| fs::write(&path, &text)) {
+                    Ok(()) => info!("Exported the packet to {}", path),
+                    Err(err) => error!("Failed to export the packet to {}: {}", path, err),
+                }
+            }
+        });
+        egui::ScrollArea::both()
+            .id_source("packet_details")
+            .show(ui, |ui| {
+                ui.add(egui::Label::new(egui::RichText::new(text).monospace()).extend());
+            });
+    }
+}
+
+// Everything known about a packet as text, shown in the details pane and used for copy and export
+fn describe(record: &Record) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "{:?} at {:.3}s, {} bytes",
+        record.direction,
+        record.elapsed.as_secs_f32(),
+        record.data.len()
+    );
+
+    if let Ok((packet_type, payload)) = parse_message(&record.data) {
+        let _ = writeln!(text, "Type: {:?}", packet_type);
+        match packet_type {
+            EPacketType::NetMessageGenericText | EPacketType::NetMessageGameMessage => {
+                let message = TextPacket::deserialize(&String::from_utf8_lossy(payload));
+                for (key, value) in message.entries() {
+                    let _ = writeln!(text, "  {}: {}", key, value);
+                }
+            }
+            EPacketType::NetMessageGamePacket => match TankPacketType::deserialize(payload) {
+                Ok(pkt) => describe_tank_packet(&mut text, &pkt),
+                Err(err) => {
+                    let _ = writeln!(text, "Malformed tank packet: {}", err);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    let _ = writeln!(text, "\nHex:");
+    for (index, chunk) in record.data.chunks(16).enumerate() {
+        let ascii = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        let hex = chunk
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(text, "{:08x}  {:<47}  {}", index * 16, hex, ascii);
+    }
+    text
+}
+
+fn describe_tank_packet(text: &mut String, pkt: &TankPacketType) {
+    let _ = writeln!(text, "Tank packet: {:?}", pkt.packet_type);
+    let fields = [
+        ("net_id", pkt.net_id.to_string()),
+        ("flags", format!("{:#010x}", pkt.flags)),
+        ("value", pkt.value.to_string()),
+        ("vector", format!("{}, {}", pkt.vector_x, pkt.vector_y)),
+        ("vector2", format!("{}, {}", pkt.vector_x2, pkt.vector_y2)),
+        ("int", format!("{}, {}", pkt.int_x, pkt.int_y)),
+        (
+            "unk",
+            format!(
+                "{} {} {} {} {} {}",
+                pkt.unk1, pkt.unk2, pkt.unk3, pkt.unk4, pkt.unk6, pkt.unk12
+            ),
+        ),
+        (
+            "extended_data",
+            format!("{} bytes", pkt.extended_data.len()),
+        ),
+    ];
+    for (name, value) in fields {
+        let _ = writeln!(text, "  {}: {}", name, value);
+    }
+
+    if pkt.packet_type == ETankPacketType::NetGamePacketCallFunction {
+        match VariantList::deserialize(&pkt.extended_data) {
+            Ok(variants) => {
+                let _ = writeln!(text, "Variants:");
+                for (index, variant) in variants.iter().enumerate() {
+                    let _ = writeln!(text, "  [{}] {:?}", index, variant);
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(text, "Malformed variant list: {}", err);
+            }
+        }
+    }
+}
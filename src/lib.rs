@@ -0,0 +1,6 @@
+pub mod bot;
+pub mod enet_proxy;
+pub mod manager;
+pub mod mock_server;
+pub mod types;
+pub mod utils;
@@ -1,22 +1,32 @@
-mod bot;
 mod gui;
-mod manager;
-mod types;
-mod utils;
 
 use std::fs;
+use std::time::Duration;
 
 use eframe::egui::{self, include_image, IconData, ViewportBuilder};
 use gui::{
     add_bot_dialog::AddBotDialog, bot_menu::BotMenu, item_database::ItemDatabase, navbar::Navbar,
+    packet_inspector::PacketInspector,
 };
 use manager::Manager;
+use mori::{bot, enet_proxy, manager, mock_server, types, utils};
 use serde::{Deserialize, Serialize};
 use types::e_login_method::ELoginMethod;
+use types::endpoints::Endpoints;
+use types::proxy::Proxy;
+use types::reconnect_policy::ReconnectPolicy;
 
 #[derive(Serialize, Deserialize)]
 struct Data {
     bots: Vec<Bot>,
+    #[serde(default)]
+    endpoints: Endpoints,
+    #[serde(default = "default_reactor_threads")]
+    reactor_threads: usize,
+}
+
+fn default_reactor_threads() -> usize {
+    manager::DEFAULT_REACTOR_THREADS
 }
 
 #[derive(Serialize, Deserialize)]
@@ -25,9 +35,44 @@ struct Bot {
     password: String,
     code: String,
     method: ELoginMethod,
+    #[serde(default)]
+    reconnect_policy: ReconnectPolicy,
+    #[serde(default)]
+    proxy: Option<Proxy>,
 }
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.len() == 3 && args[1] == "--mock-server" {
+        if let Err(err) = mock_server::run(&args[2]) {
+            spdlog::error!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if (args.len() == 3 || args.len() == 4) && args[1] == "--proxy" {
+        let listen_port = match args.get(3).map(|port| port.parse::<u16>()) {
+            Some(Ok(port)) => port,
+            Some(Err(err)) => {
+                spdlog::error!("Invalid listen port {}: {}", args[3], err);
+                std::process::exit(1);
+            }
+            None => 17091,
+        };
+        if let Err(err) = enet_proxy::run(&args[2], listen_port) {
+            spdlog::error!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.len() == 3 && args[1] == "--replay" {
+        if let Err(err) = bot::replay::run(&args[2]) {
+            spdlog::error!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let options = eframe::NativeOptions {
         centered: true,
         viewport: ViewportBuilder::default()
@@ -50,28 +95,34 @@ struct App {
     add_bot_dialog: AddBotDialog,
     bots: Vec<Bot>,
     bot_menu: BotMenu,
+    packet_inspector: PacketInspector,
 }
 
 impl App {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let mut manager = Manager::new().unwrap();
-
         let data = match fs::read_to_string("data.json") {
             Ok(data) => data,
             Err(_) => {
-                let data = Data { bots: vec![] };
+                let data = Data {
+                    bots: vec![],
+                    endpoints: Endpoints::default(),
+                    reactor_threads: default_reactor_threads(),
+                };
                 let json = serde_json::to_string_pretty(&data).unwrap();
                 fs::write("data.json", &json).unwrap();
                 json
             }
         };
         let json = serde_json::from_str::<Data>(&data).unwrap();
+        let mut manager = Manager::new(json.endpoints.clone(), json.reactor_threads).unwrap();
         for bot in &json.bots {
             manager.add_bot(
                 bot.username.clone(),
                 bot.password.clone(),
                 bot.code.clone(),
                 bot.method.clone(),
+                bot.reconnect_policy.clone(),
+                bot.proxy.clone(),
             );
         }
 
@@ -82,6 +133,7 @@ impl App {
             add_bot_dialog: Default::default(),
             bots: json.bots,
             bot_menu: Default::default(),
+            packet_inspector: Default::default(),
         }
     }
 }
@@ -93,13 +145,20 @@ impl eframe::App for App {
             self.navbar.render(ui, &mut self.add_bot_dialog);
             ui.separator();
             if self.navbar.current_menu == "bots" {
-                self.bot_menu.render(ui, &self.bots, &self.manager);
+                self.bot_menu.render(ui, &mut self.bots, &mut self.manager);
             } else if self.navbar.current_menu == "item_database" {
                 self.item_database.render(ui, &mut self.manager, ctx);
+            } else if self.navbar.current_menu == "packets" {
+                self.packet_inspector
+                    .render(ui, &self.bot_menu.selected_bot, &mut self.manager);
             } else {
                 ui.label("Not implemented yet");
             }
         });
         self.add_bot_dialog.render(&mut self.manager, ctx);
+        // Nothing else repaints while a restarted bot waits for its old threads
+        if self.manager.update() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
     }
 }
@@ -1,26 +1,49 @@
-use std::sync::{Arc, Mutex, RwLock};
-use std::thread::{spawn, JoinHandle};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::bot::{self, Bot};
+use crate::bot::{
+    self,
+    capture::Recorder,
+    command::Command,
+    item_actions::ItemActionResult,
+    reactor::{Reactor, ReactorPool},
+    supervisor, Bot,
+};
+use crate::types::bot_state::BotState;
 use crate::types::e_login_method::ELoginMethod;
+use crate::types::endpoints::Endpoints;
+use crate::types::proxy::Proxy;
+use crate::types::reconnect_policy::ReconnectPolicy;
 use gtitem_r::structs::ItemDatabase;
 use spdlog::prelude::*;
 
+// Network threads shared by all bots, unless data.json asks for another amount
+pub const DEFAULT_REACTOR_THREADS: usize = 2;
+
 pub struct Manager {
-    pub bots: Vec<(Arc<Mutex<Bot>>, JoinHandle<()>)>,
+    pub bots: Vec<(Arc<Mutex<Bot>>, Reactor)>,
     pub items_database: Arc<ItemDatabase>,
+    reactor_pool: ReactorPool,
+    endpoints: Endpoints,
+    // Stopped bots that log in again once the threads of their last run are done
+    restarting: Vec<Arc<Mutex<Bot>>>,
 }
 
 impl Manager {
-    pub fn new() -> Result<Manager, String> {
+    pub fn new(endpoints: Endpoints, reactor_threads: usize) -> Result<Manager, String> {
         info!("Loading items database...");
         let item_database = gtitem_r::load_from_file("items.dat").unwrap();
         info!("Successfully loaded items database");
+        let reactor_pool = ReactorPool::new(reactor_threads)?;
         info!("Initialized Manager");
 
         Ok(Manager {
             bots: vec![],
             items_database: Arc::new(item_database),
+            reactor_pool,
+            endpoints,
+            restarting: vec![],
         })
     }
 }
@@ -32,6 +55,8 @@ impl Manager {
         password: String,
         code: String,
         method: ELoginMethod,
+        reconnect_policy: ReconnectPolicy,
+        proxy: Option<Proxy>,
     ) {
         if method == ELoginMethod::LEGACY {
             info!("Adding bot: {}", username);
@@ -39,21 +64,158 @@ impl Manager {
             info!("Adding bot with method: {:?}", method);
         }
         let items_database_clone = Arc::clone(&self.items_database);
-        let new_bot = Arc::new(Mutex::new(Bot::new(
+        let mut bot = Bot::new(
             username,
             password,
             code,
             method,
+            reconnect_policy,
+            proxy,
             items_database_clone,
-        )));
-        let newbot_clone = Arc::clone(&new_bot);
+        );
+        bot.info.endpoints = self.endpoints.clone();
+        let new_bot = Arc::new(Mutex::new(bot));
+        let reactor = self.reactor_pool.least_loaded();
+        start(&new_bot, &reactor);
+        self.bots.push((new_bot, reactor));
+    }
+    pub fn remove_bot(&mut self, username: &str) {
+        self.stop_bot(username);
+        if let Some(index) = self.get_bot_index(username) {
+            let (bot, _) = self.bots.remove(index);
+            self.restarting
+                .retain(|restarting| !Arc::ptr_eq(restarting, &bot));
+            info!("Removed bot: {}", username);
+        }
+    }
+    // Only asks the bot to stop, its threads finish on their own since one may be stuck in a login request
+    pub fn stop_bot(&mut self, username: &str) {
+        if let Some(bot) = self.get_bot(username) {
+            let mut bot = bot.lock().unwrap();
+            bot.state.transition(BotState::Stopped);
+            bot.command_queue.push_back(Command::Disconnect);
+            info!("Stopping bot: {}", username);
+        }
+    }
+    pub fn restart_bot(&mut self, username: &str) {
+        let bot = match self.get_bot(username) {
+            Some(bot) => Arc::clone(bot),
+            None => return,
+        };
+        self.stop_bot(username);
+        if !self
+            .restarting
+            .iter()
+            .any(|restarting| Arc::ptr_eq(restarting, &bot))
+        {
+            self.restarting.push(bot);
+        }
+        self.update();
+    }
+    // Logs restarting bots back in once their old threads are done, called every frame.
+    // Returns true while some bot is still waiting.
+    pub fn update(&mut self) -> bool {
+        let mut waiting = vec![];
+        for bot in std::mem::take(&mut self.restarting) {
+            if !bot.lock().unwrap().threads_finished() {
+                waiting.push(bot);
+                continue;
+            }
+            let reactor = match self.bots.iter().find(|(other, _)| Arc::ptr_eq(other, &bot)) {
+                Some((_, reactor)) => reactor.clone(),
+                None => continue,
+            };
+            let username = {
+                let mut bot = bot.lock().unwrap();
+                bot.reset();
+                bot.info.username.clone()
+            };
+            start(&bot, &reactor);
+            info!("Restarted bot: {}", username);
+        }
+        self.restarting = waiting;
+        !self.restarting.is_empty()
+    }
+    pub fn set_bot_paused(&self, username: &str, paused: bool) {
+        if let Some(bot) = self.get_bot(username) {
+            bot.lock().unwrap().state.paused = paused;
+        }
+    }
+
+    // Starts writing everything the bot sends and receives to captures/, returns the file path
+    pub fn start_capture(&self, username: &str) -> Result<String, String> {
+        let bot = self
+            .get_bot(username)
+            .ok_or(format!("No bot named {}", username))?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let path = format!("captures/{}-{}.cap", username, timestamp);
+        let recorder =
+            Recorder::create(&path).map_err(|err| format!("Failed to create {}: {}", path, err))?;
+        info!("Capturing packets of {} to {}", username, path);
+        bot.lock().unwrap().capture = Some(Arc::new(recorder));
+        Ok(path)
+    }
+
+    pub fn stop_capture(&self, username: &str) {
+        if let Some(bot) = self.get_bot(username) {
+            if let Some(recorder) = bot.lock().unwrap().capture.take() {
+                info!("Stopped capturing packets to {}", recorder.path());
+            }
+        }
+    }
+
+    // Queued like any other command, the receiver gets how many are left once the server confirmed
+    pub fn drop_item(
+        &self,
+        username: &str,
+        item_id: u16,
+        amount: u8,
+    ) -> Result<Receiver<ItemActionResult>, String> {
+        let (reply, receiver) = mpsc::channel();
+        self.queue(
+            username,
+            Command::Drop {
+                item_id,
+                amount,
+                reply: Some(reply),
+            },
+        )?;
+        Ok(receiver)
+    }
+
+    pub fn trash_item(
+        &self,
+        username: &str,
+        item_id: u16,
+        amount: u8,
+    ) -> Result<Receiver<ItemActionResult>, String> {
+        let (reply, receiver) = mpsc::channel();
+        self.queue(
+            username,
+            Command::Trash {
+                item_id,
+                amount,
+                reply: Some(reply),
+            },
+        )?;
+        Ok(receiver)
+    }
 
-        let handle = spawn(move || {
-            bot::login(newbot_clone);
-        });
-        self.bots.push((new_bot, handle));
+    fn queue(&self, username: &str, command: Command) -> Result<(), String> {
+        let bot = self
+            .get_bot(username)
+            .ok_or(format!("No bot named {}", username))?;
+        bot.lock().unwrap().command_queue.push_back(command);
+        Ok(())
+    }
+    fn get_bot_index(&self, username: &str) -> Option<usize> {
+        self.bots
+            .iter()
+            .position(|(bot, _)| bot.lock().unwrap().info.username == username)
     }
-    pub fn remove_bot(&mut self, username: &str) {}
     pub fn get_bot(&self, username: &str) -> Option<&Arc<Mutex<Bot>>> {
         for (bot, _) in &self.bots {
             let bot_mutex = bot.lock().unwrap();
@@ -64,3 +226,11 @@ impl Manager {
         None
     }
 }
+
+fn start(bot_mutex: &Arc<Mutex<Bot>>, reactor: &Reactor) {
+    let bot_clone = Arc::clone(bot_mutex);
+    let reactor_clone = reactor.clone();
+    supervisor::spawn_isolated(Arc::clone(bot_mutex), reactor.clone(), move || {
+        bot::login(bot_clone, reactor_clone)
+    });
+}
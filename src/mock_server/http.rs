@@ -0,0 +1,90 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread::spawn;
+
+use spdlog::{info, warn};
+
+// Answers just enough of the login endpoints for a bot to get a token and the server address.
+// Point the endpoints in data.json at it, see endpoints().
+pub fn spawn_server(http_port: u16, enet_port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, http_port))
+        .map_err(|err| format!("Failed to bind the mock HTTP server: {}", err))?;
+    spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(err) = handle(stream, http_port, enet_port) {
+                        warn!("Mock HTTP request failed: {}", err);
+                    }
+                }
+                Err(err) => warn!("Mock HTTP accept failed: {}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+pub fn endpoints(http_port: u16) -> String {
+    let base = base_url(http_port);
+    format!(
+        "\"endpoints\": {{ \"server_data\": \"{}/growtopia/server_data.php\", \"login\": \"{}\", \"ubisoft\": \"{}\" }}",
+        base, base, base
+    )
+}
+
+fn base_url(http_port: u16) -> String {
+    format!("http://127.0.0.1:{}", http_port)
+}
+
+fn handle(mut stream: TcpStream, http_port: u16, enet_port: u16) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            if key.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    info!("Mock HTTP: {}", request_line.trim());
+    let base = base_url(http_port);
+    let response = if path.starts_with("/growtopia/server_data.php") {
+        format!(
+            "server|127.0.0.1\nport|{}\ntype|1\nmeta|mock\nRTENDMARKERBS1001",
+            enet_port
+        )
+    } else if path.starts_with("/player/login/dashboard?valKey") {
+        "{\"status\":\"success\",\"token\":\"mock-token\"}".to_string()
+    } else if path.starts_with("/player/login/dashboard") {
+        // get_oauth_links expects apple, google and legacy in this order
+        format!(
+            "<a href=\"{0}/apple/redirect?token=mock\"></a><a href=\"{0}/google/redirect?token=mock\"></a><a href=\"{0}/player/growid/login?token=mock\"></a>",
+            base
+        )
+    } else if path.starts_with("/player/growid/login/validate") {
+        "{\"status\":\"success\",\"token\":\"mock-token\"}".to_string()
+    } else if path.starts_with("/player/growid/login") {
+        "<input name=\"_token\" type=\"hidden\" value=\"mock\">".to_string()
+    } else if path.starts_with("/v3/profiles/sessions") {
+        "{\"ticket\":\"mock-ticket\"}".to_string()
+    } else {
+        return stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.len(),
+        response
+    )
+}
@@ -0,0 +1,243 @@
+mod http;
+mod script;
+
+use std::fs;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use enet::*;
+use script::{Expect, Script, Step};
+use spdlog::info;
+
+use crate::types::e_packet_type::EPacketType;
+use crate::types::e_tank_packet_type::ETankPacketType;
+use crate::types::tank_packet_type::TankPacketType;
+use crate::utils::variant::VariantList;
+
+// Plays a scripted game server session against a bot and checks what the bot sends back,
+// so the login, redirect and packet handling can be exercised without the live game.
+// Run with `Mori --mock-server <script.json>`.
+pub fn run(script_path: &str) -> Result<(), String> {
+    let enet = Enet::new().map_err(|err| format!("Failed to initialize ENet: {:?}", err))?;
+    run_with_enet(&enet, script_path)
+}
+
+// ENet can only be initialized once per process, tests share theirs with the bot's reactors
+pub fn run_with_enet(enet: &Enet, script_path: &str) -> Result<(), String> {
+    let script = fs::read_to_string(script_path)
+        .map_err(|err| format!("Failed to read {}: {}", script_path, err))?;
+    let script = serde_json::from_str::<Script>(&script)
+        .map_err(|err| format!("Failed to parse {}: {}", script_path, err))?;
+
+    http::spawn_server(script.http_port, script.enet_port)?;
+    info!(
+        "Mock server listening, put this in data.json: {}",
+        http::endpoints(script.http_port)
+    );
+
+    let host = enet
+        .create_host::<()>(
+            Some(&Address::new(Ipv4Addr::LOCALHOST, script.enet_port)),
+            4,
+            ChannelLimit::Limited(2),
+            BandwidthLimit::Unlimited,
+            BandwidthLimit::Unlimited,
+            true,
+            false,
+        )
+        .map_err(|err| format!("Failed to create the mock ENet host: {}", err))?;
+
+    let mut server = MockServer {
+        host,
+        peer: None,
+        timeout: Duration::from_millis(script.timeout_ms),
+    };
+    server.wait_for_connect()?;
+    for (index, step) in script.steps.iter().enumerate() {
+        info!("Step {}: {:?}", index, step);
+        server
+            .run_step(step)
+            .map_err(|err| format!("Step {} ({:?}) failed: {}", index, step, err))?;
+    }
+    info!("Script passed");
+    Ok(())
+}
+
+struct MockServer {
+    host: Host<()>,
+    peer: Option<PeerID>,
+    timeout: Duration,
+}
+
+impl MockServer {
+    fn run_step(&mut self, step: &Step) -> Result<(), String> {
+        match step {
+            Step::WaitForConnect => self.wait_for_connect(),
+            Step::Hello => self.send(EPacketType::NetMessageServerHello, Vec::new()),
+            Step::GameMessage(message) => self.send(
+                EPacketType::NetMessageGameMessage,
+                message.as_bytes().to_vec(),
+            ),
+            Step::CallFunction(variants) => {
+                let mut pkt = TankPacketType::new();
+                pkt.packet_type = ETankPacketType::NetGamePacketCallFunction;
+                pkt.net_id = u32::MAX;
+                pkt.extended_data = VariantList::from(variants.clone())
+                    .serialize()
+                    .map_err(|err| format!("Failed to serialize variants: {}", err))?;
+                self.send_tank_packet(&pkt)
+            }
+            Step::MapData(path) => {
+                let mut pkt = TankPacketType::new();
+                pkt.packet_type = ETankPacketType::NetGamePacketSendMapData;
+                pkt.extended_data = fs::read(path)
+                    .map_err(|err| format!("Failed to read map data {}: {}", path, err))?;
+                self.send_tank_packet(&pkt)
+            }
+            Step::InventoryState(items) => {
+                // Laid out the way Inventory::parse reads it
+                let mut data = vec![0];
+                data.extend_from_slice(&(items.len() as u32).to_le_bytes());
+                data.extend_from_slice(&(items.len() as u16).to_le_bytes());
+                for (id, amount) in items {
+                    data.extend_from_slice(&id.to_le_bytes());
+                    // Nothing is equipped
+                    data.extend_from_slice(&[*amount, 0]);
+                }
+                let mut pkt = TankPacketType::new();
+                pkt.packet_type = ETankPacketType::NetGamePacketSendInventoryState;
+                pkt.extended_data = data;
+                self.send_tank_packet(&pkt)
+            }
+            Step::PingRequest => {
+                let mut pkt = TankPacketType::new();
+                pkt.packet_type = ETankPacketType::NetGamePacketPingRequest;
+                self.send_tank_packet(&pkt)
+            }
+            Step::Expect(expect) => self.expect(expect),
+            Step::Sleep(ms) => {
+                let deadline = Instant::now() + Duration::from_millis(*ms);
+                while Instant::now() < deadline {
+                    self.poll(deadline - Instant::now())?;
+                }
+                Ok(())
+            }
+            Step::Disconnect => {
+                let peer_id = self.peer.ok_or("no bot is connected")?;
+                if let Some(peer) = self.host.peer_mut(peer_id) {
+                    peer.disconnect(0);
+                }
+                let deadline = Instant::now() + self.timeout;
+                while self.peer.is_some() {
+                    if Instant::now() >= deadline {
+                        return Err("the bot didn't acknowledge the disconnect".to_string());
+                    }
+                    self.poll(deadline - Instant::now())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn wait_for_connect(&mut self) -> Result<(), String> {
+        info!("Waiting for a bot to connect");
+        let previous = self.peer;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if self.peer.is_some() && self.peer != previous {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err("no bot connected in time".to_string());
+            }
+            self.poll(deadline - Instant::now())?;
+        }
+    }
+
+    fn expect(&mut self, expect: &Expect) -> Result<(), String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(format!("the bot never sent {:?}", expect));
+            }
+            if let Some(data) = self.poll(deadline - Instant::now())? {
+                if matches(expect, &data) {
+                    return Ok(());
+                }
+                info!("Skipping {} bytes from the bot", data.len());
+            }
+        }
+    }
+
+    // Services the host once, returns the data of a received packet if there was one
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, String> {
+        let event = self
+            .host
+            .service(timeout.min(Duration::from_millis(100)))
+            .map_err(|err| format!("Service failed: {}", err))?;
+        let event = match event {
+            Some(event) => event,
+            None => return Ok(None),
+        };
+        let peer_id = event.peer_id();
+        match event.take_kind() {
+            EventKind::Connect => {
+                info!("Bot connected");
+                self.peer = Some(peer_id);
+                Ok(None)
+            }
+            EventKind::Disconnect { .. } => {
+                info!("Bot disconnected");
+                if self.peer == Some(peer_id) {
+                    self.peer = None;
+                }
+                Ok(None)
+            }
+            EventKind::Receive { packet, .. } => Ok(Some(packet.data().to_vec())),
+        }
+    }
+
+    fn send(&mut self, packet_type: EPacketType, message: Vec<u8>) -> Result<(), String> {
+        let mut data = (packet_type as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(&message);
+        let peer_id = self.peer.ok_or("no bot is connected")?;
+        let peer = self
+            .host
+            .peer_mut(peer_id)
+            .ok_or("the bot's peer is gone")?;
+        let packet = Packet::new(data, PacketMode::ReliableSequenced)
+            .map_err(|err| format!("Failed to create a packet: {}", err))?;
+        peer.send_packet(packet, 0)
+            .map_err(|err| format!("Failed to send: {}", err))?;
+        self.host.flush();
+        Ok(())
+    }
+
+    fn send_tank_packet(&mut self, pkt: &TankPacketType) -> Result<(), String> {
+        self.send(EPacketType::NetMessageGamePacket, pkt.serialize())
+    }
+}
+
+fn matches(expect: &Expect, data: &[u8]) -> bool {
+    if data.len() < 4 {
+        return false;
+    }
+    let packet_type = EPacketType::from(u32::from_le_bytes([data[0], data[1], data[2], data[3]]));
+    let message = &data[4..];
+    match expect {
+        Expect::Text(text) => {
+            packet_type == EPacketType::NetMessageGenericText
+                && String::from_utf8_lossy(message).contains(text.as_str())
+        }
+        Expect::GameMessage(text) => {
+            packet_type == EPacketType::NetMessageGameMessage
+                && String::from_utf8_lossy(message).contains(text.as_str())
+        }
+        Expect::TankPacket(expected) => {
+            packet_type == EPacketType::NetMessageGamePacket
+                && TankPacketType::deserialize(message)
+                    .map(|pkt| pkt.packet_type == *expected)
+                    .unwrap_or(false)
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use serde::Deserialize;
+
+use crate::types::e_tank_packet_type::ETankPacketType;
+use crate::utils::variant::Variant;
+
+#[derive(Debug, Deserialize)]
+pub struct Script {
+    #[serde(default = "default_enet_port")]
+    pub enet_port: u16,
+    #[serde(default = "default_http_port")]
+    pub http_port: u16,
+    // How long an expect or a wait may take before the script fails
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    pub steps: Vec<Step>,
+}
+
+// One thing the server does, in order. Steps that send need a connected bot.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    // Waits for the bot to (re)connect, e.g. after a redirect
+    WaitForConnect,
+    Hello,
+    GameMessage(String),
+    CallFunction(Vec<Variant>),
+    // Path to a raw world dump, sent as is
+    MapData(String),
+    // (item id, amount) pairs
+    InventoryState(Vec<(u16, u8)>),
+    PingRequest,
+    Expect(Expect),
+    // Keeps servicing the connection without expecting anything
+    Sleep(u64),
+    Disconnect,
+}
+
+// Waits for a packet from the bot that matches, anything else it sends in the meantime is skipped
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expect {
+    Text(String),
+    GameMessage(String),
+    TankPacket(ETankPacketType),
+}
+
+fn default_enet_port() -> u16 {
+    17091
+}
+
+fn default_http_port() -> u16 {
+    8080
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
@@ -1,6 +1,20 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::{e_login_method::ELoginMethod, login_info::LoginInfo};
+use spdlog::{info, warn};
+
+use crate::utils::http::{HttpClient, UreqClient};
+
+use super::{
+    bot_state::BotState,
+    crash_report::CrashReport,
+    e_login_method::ELoginMethod,
+    endpoints::Endpoints,
+    login_info::LoginInfo,
+    proxy::Proxy,
+    reconnect_policy::{DisconnectReason, ReconnectPolicy},
+};
 
 pub struct Info {
     pub display_name: String,
@@ -13,7 +27,10 @@ pub struct Info {
     pub login_info: LoginInfo,
     pub ping: u32,
     pub parsed_server_data: HashMap<String, String>,
-    pub status: String,
+    pub reconnect_policy: ReconnectPolicy,
+    pub proxy: Option<Proxy>,
+    pub endpoints: Endpoints,
+    pub http: Arc<dyn HttpClient>,
 }
 
 impl Default for Info {
@@ -29,19 +46,78 @@ impl Default for Info {
             login_info: LoginInfo::default(),
             ping: 0,
             parsed_server_data: HashMap::new(),
-            status: String::new(),
+            reconnect_policy: ReconnectPolicy::default(),
+            proxy: None,
+            endpoints: Endpoints::default(),
+            http: Arc::new(UreqClient::new(None)),
         }
     }
 }
 
-#[derive(Default)]
 pub struct State {
     pub net_id: u32,
     pub gems: i32,
-    pub is_redirect: bool,
-    pub is_running: bool,
-    pub is_banned: bool,
-    pub is_ingame: bool,
+    pub disconnect_reason: Option<DisconnectReason>,
+    pub reconnect_attempts: u32,
+    pub paused: bool,
+    pub crash: Option<CrashReport>,
+    pub crash_count: u32,
+    // How close, in tiles, the bot gets to a dropped object before picking it up
+    pub collect_range: f32,
+    current: BotState,
+    previous: BotState,
+    changed_at: Instant,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            net_id: 0,
+            gems: 0,
+            disconnect_reason: None,
+            reconnect_attempts: 0,
+            paused: false,
+            crash: None,
+            crash_count: 0,
+            collect_range: 1.5,
+            current: BotState::Idle,
+            previous: BotState::Idle,
+            changed_at: Instant::now(),
+        }
+    }
+}
+
+impl State {
+    pub fn current(&self) -> BotState {
+        self.current
+    }
+
+    pub fn previous(&self) -> BotState {
+        self.previous
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.changed_at.elapsed()
+    }
+
+    pub fn transition(&mut self, next: BotState) -> bool {
+        if self.current == next {
+            return true;
+        }
+        if !self.current.can_transition_to(next) {
+            warn!("Invalid state transition: {} -> {}", self.current, next);
+            return false;
+        }
+        info!("State: {} -> {}", self.current, next);
+        self.previous = self.current;
+        self.current = next;
+        self.changed_at = Instant::now();
+        if next == BotState::InGame {
+            self.disconnect_reason = None;
+            self.reconnect_attempts = 0;
+        }
+        true
+    }
 }
 
 pub struct Server {
@@ -58,7 +134,7 @@ impl Default for Server {
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Debug, Default, Clone)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
@@ -0,0 +1,182 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BotState {
+    #[default]
+    Idle,
+    FetchingServerData,
+    Authenticating,
+    Connecting,
+    Redirecting,
+    InGame,
+    InWorld,
+    Reconnecting,
+    Banned,
+    Stopped,
+    Crashed,
+}
+
+impl BotState {
+    pub fn can_transition_to(self, next: BotState) -> bool {
+        use BotState::*;
+
+        // A banned bot only ever gets stopped, crashing would restart it
+        if self == Banned {
+            return next == Stopped;
+        }
+        if next == Stopped || next == Crashed {
+            return true;
+        }
+        match self {
+            Idle => matches!(next, FetchingServerData),
+            FetchingServerData => matches!(next, Authenticating | Connecting | Reconnecting),
+            Authenticating => matches!(next, FetchingServerData | Connecting | Reconnecting),
+            Connecting => matches!(next, Redirecting | InGame | Reconnecting | Banned),
+            Redirecting => matches!(next, InGame | InWorld | Reconnecting | Banned),
+            InGame => matches!(next, InWorld | Redirecting | Reconnecting | Banned),
+            InWorld => matches!(next, InGame | Redirecting | Reconnecting | Banned),
+            Reconnecting => matches!(
+                next,
+                FetchingServerData | Authenticating | Connecting | Banned
+            ),
+            Banned => false,
+            Stopped | Crashed => matches!(next, Idle),
+        }
+    }
+
+    // The event loop keeps running in every state except these
+    pub fn is_running(self) -> bool {
+        !matches!(
+            self,
+            BotState::Idle | BotState::Banned | BotState::Stopped | BotState::Crashed
+        )
+    }
+}
+
+impl fmt::Display for BotState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BotState::Idle => "Idle",
+            BotState::FetchingServerData => "Fetching server data",
+            BotState::Authenticating => "Authenticating",
+            BotState::Connecting => "Connecting",
+            BotState::Redirecting => "Redirecting",
+            BotState::InGame => "In game",
+            BotState::InWorld => "In world",
+            BotState::Reconnecting => "Reconnecting",
+            BotState::Banned => "Banned",
+            BotState::Stopped => "Stopped",
+            BotState::Crashed => "Crashed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BotState::*;
+    use super::*;
+
+    const ALL: [BotState; 11] = [
+        Idle,
+        FetchingServerData,
+        Authenticating,
+        Connecting,
+        Redirecting,
+        InGame,
+        InWorld,
+        Reconnecting,
+        Banned,
+        Stopped,
+        Crashed,
+    ];
+
+    #[test]
+    fn allows_the_login_and_reconnect_paths() {
+        let allowed = [
+            (Idle, FetchingServerData),
+            (FetchingServerData, Authenticating),
+            (Authenticating, FetchingServerData),
+            (FetchingServerData, Connecting),
+            (Connecting, Redirecting),
+            (Connecting, InGame),
+            (Redirecting, InGame),
+            (InGame, InWorld),
+            (InWorld, InGame),
+            (InWorld, Redirecting),
+            (InWorld, Reconnecting),
+            (Connecting, Reconnecting),
+            (Reconnecting, FetchingServerData),
+            (Reconnecting, Connecting),
+            (Connecting, Banned),
+            (Reconnecting, Banned),
+            (Stopped, Idle),
+            (Crashed, Idle),
+        ];
+        for (from, to) in allowed {
+            assert!(from.can_transition_to(to), "{} -> {}", from, to);
+        }
+    }
+
+    #[test]
+    fn forbids_skipping_steps() {
+        let forbidden = [
+            (Idle, Connecting),
+            (Idle, InGame),
+            (FetchingServerData, InGame),
+            (Connecting, InWorld),
+            (InGame, Connecting),
+            (Reconnecting, InGame),
+            (Stopped, Connecting),
+            (Crashed, Reconnecting),
+        ];
+        for (from, to) in forbidden {
+            assert!(!from.can_transition_to(to), "{} -> {}", from, to);
+        }
+    }
+
+    #[test]
+    fn every_state_but_banned_can_stop_or_crash() {
+        for state in ALL {
+            assert!(state.can_transition_to(Stopped), "{} -> Stopped", state);
+            assert_eq!(
+                state.can_transition_to(Crashed),
+                state != Banned,
+                "{}",
+                state
+            );
+        }
+    }
+
+    #[test]
+    fn banned_only_stops() {
+        for next in ALL {
+            assert_eq!(
+                Banned.can_transition_to(next),
+                next == Stopped,
+                "Banned -> {}",
+                next
+            );
+        }
+    }
+
+    #[test]
+    fn only_active_states_are_running() {
+        let running = ALL
+            .into_iter()
+            .filter(|state| state.is_running())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            running,
+            vec![
+                FetchingServerData,
+                Authenticating,
+                Connecting,
+                Redirecting,
+                InGame,
+                InWorld,
+                Reconnecting,
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use crate::utils::text_parse;
+use crate::utils::variant::VariantList;
+
+use super::packet_error::PacketError;
+
+// A NetGamePacketCallFunction with its arguments checked and named.
+// The first variant is the function name, the rest are its arguments in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallFunction {
+    OnSendToServer {
+        port: i32,
+        token: i32,
+        user_id: i32,
+        ip: String,
+        door_id: String,
+        uuid: String,
+    },
+    OnSuperMainStartAcceptLogon,
+    OnCountryState(String),
+    OnDialogRequest(String),
+    OnSetBux(i32),
+    OnConsoleMessage(String),
+    OnSetPos {
+        x: f32,
+        y: f32,
+    },
+    ShowStartFTUEPopup,
+    OnFtueButtonDataSet {
+        unknown_1: i32,
+        current_progress: i32,
+        total_progress: i32,
+        info: String,
+    },
+    OnHideMenusRequest,
+    OnSpawn {
+        net_id: u32,
+        // Every key of the spawn message, e.g. name, country, posXY
+        data: HashMap<String, String>,
+    },
+    OnRemove {
+        net_id: u32,
+    },
+    OnTalkBubble(String),
+    OnTextOverlay(String),
+    OnClearTutorialArrow(String),
+    // Item ids, sent as floats three to a variant
+    OnSetClothing {
+        hair: u16,
+        shirt: u16,
+        pants: u16,
+        feet: u16,
+        face: u16,
+        hand: u16,
+        back: u16,
+        mask: u16,
+        necklace: u16,
+        skin_color: u32,
+    },
+    OnRequestWorldSelectMenu(String),
+    // Anything not parsed above, kept whole so it can still be logged or inspected
+    Unknown(String, VariantList),
+}
+
+impl CallFunction {
+    pub fn parse(variants: VariantList) -> Result<CallFunction, PacketError> {
+        let args = Args(&variants);
+        let name = args.string(0)?.to_string();
+        let function = match name.as_str() {
+            "OnSendToServer" => {
+                let server_data = args.string(4)?;
+                let parts = text_parse::parse_and_store_as_vec(server_data);
+                if parts.len() < 3 {
+                    return Err(PacketError::Malformed(format!(
+                        "OnSendToServer server data: {}",
+                        server_data
+                    )));
+                }
+                CallFunction::OnSendToServer {
+                    port: args.int(1)?,
+                    token: args.int(2)?,
+                    user_id: args.int(3)?,
+                    ip: parts[0].to_string(),
+                    door_id: parts[1].to_string(),
+                    uuid: parts[2].to_string(),
+                }
+            }
+            "OnSuperMainStartAcceptLogonHrdxs47254722215a" => {
+                CallFunction::OnSuperMainStartAcceptLogon
+            }
+            "OnCountryState" => CallFunction::OnCountryState(args.string(1)?.to_string()),
+            "OnDialogRequest" => CallFunction::OnDialogRequest(args.string(1)?.to_string()),
+            "OnSetBux" => CallFunction::OnSetBux(args.int(1)?),
+            "OnConsoleMessage" => CallFunction::OnConsoleMessage(args.string(1)?.to_string()),
+            "OnSetPos" => {
+                let (x, y) = args.vec2(1)?;
+                CallFunction::OnSetPos { x, y }
+            }
+            "ShowStartFTUEPopup" => CallFunction::ShowStartFTUEPopup,
+            "OnFtueButtonDataSet" => CallFunction::OnFtueButtonDataSet {
+                unknown_1: args.int(1)?,
+                current_progress: args.int(2)?,
+                total_progress: args.int(3)?,
+                info: args.string(4)?.to_string(),
+            },
+            "OnHideMenusRequest" => CallFunction::OnHideMenusRequest,
+            "OnSpawn" => {
+                let message = args.string(1)?;
+                let data = text_parse::parse_and_store_as_map(message);
+                CallFunction::OnSpawn {
+                    net_id: net_id(&data)
+                        .ok_or(PacketError::Malformed(format!("OnSpawn: {}", message)))?,
+                    data,
+                }
+            }
+            "OnRemove" => {
+                let message = args.string(1)?;
+                CallFunction::OnRemove {
+                    net_id: net_id(&text_parse::parse_and_store_as_map(message))
+                        .ok_or(PacketError::Malformed(format!("OnRemove: {}", message)))?,
+                }
+            }
+            "OnTalkBubble" => CallFunction::OnTalkBubble(args.string(2)?.to_string()),
+            "OnTextOverlay" => CallFunction::OnTextOverlay(args.string(1)?.to_string()),
+            "OnClearTutorialArrow" => {
+                CallFunction::OnClearTutorialArrow(args.string(1)?.to_string())
+            }
+            "OnSetClothing" => {
+                let (hair, shirt, pants) = args.vec3(1)?;
+                let (feet, face, hand) = args.vec3(2)?;
+                let (back, mask, necklace) = args.vec3(3)?;
+                CallFunction::OnSetClothing {
+                    hair: hair as u16,
+                    shirt: shirt as u16,
+                    pants: pants as u16,
+                    feet: feet as u16,
+                    face: face as u16,
+                    hand: hand as u16,
+                    back: back as u16,
+                    mask: mask as u16,
+                    necklace: necklace as u16,
+                    skin_color: args.uint(4)?,
+                }
+            }
+            "OnRequestWorldSelectMenu" => {
+                CallFunction::OnRequestWorldSelectMenu(args.string(1)?.to_string())
+            }
+            _ => CallFunction::Unknown(name, variants),
+        };
+        Ok(function)
+    }
+
+    // The name the server used, also what handlers are registered under
+    pub fn name(&self) -> &str {
+        match self {
+            CallFunction::OnSendToServer { .. } => "OnSendToServer",
+            CallFunction::OnSuperMainStartAcceptLogon => {
+                "OnSuperMainStartAcceptLogonHrdxs47254722215a"
+            }
+            CallFunction::OnCountryState(_) => "OnCountryState",
+            CallFunction::OnDialogRequest(_) => "OnDialogRequest",
+            CallFunction::OnSetBux(_) => "OnSetBux",
+            CallFunction::OnConsoleMessage(_) => "OnConsoleMessage",
+            CallFunction::OnSetPos { .. } => "OnSetPos",
+            CallFunction::ShowStartFTUEPopup => "ShowStartFTUEPopup",
+            CallFunction::OnFtueButtonDataSet { .. } => "OnFtueButtonDataSet",
+            CallFunction::OnHideMenusRequest => "OnHideMenusRequest",
+            CallFunction::OnSpawn { .. } => "OnSpawn",
+            CallFunction::OnRemove { .. } => "OnRemove",
+            CallFunction::OnTalkBubble(_) => "OnTalkBubble",
+            CallFunction::OnTextOverlay(_) => "OnTextOverlay",
+            CallFunction::OnClearTutorialArrow(_) => "OnClearTutorialArrow",
+            CallFunction::OnSetClothing { .. } => "OnSetClothing",
+            CallFunction::OnRequestWorldSelectMenu(_) => "OnRequestWorldSelectMenu",
+            CallFunction::Unknown(name, _) => name,
+        }
+    }
+}
+
+// Typed access to the arguments, a missing one or one of the wrong type is an InvalidVariant
+struct Args<'a>(&'a VariantList);
+
+impl<'a> Args<'a> {
+    fn string(&self, index: usize) -> Result<&'a str, PacketError> {
+        self.0
+            .get(index)
+            .and_then(|v| v.as_str())
+            .ok_or(PacketError::InvalidVariant(index))
+    }
+
+    fn int(&self, index: usize) -> Result<i32, PacketError> {
+        self.0
+            .get(index)
+            .and_then(|v| v.as_int32())
+            .ok_or(PacketError::InvalidVariant(index))
+    }
+
+    fn uint(&self, index: usize) -> Result<u32, PacketError> {
+        self.0
+            .get(index)
+            .and_then(|v| v.as_uint32())
+            .ok_or(PacketError::InvalidVariant(index))
+    }
+
+    fn vec2(&self, index: usize) -> Result<(f32, f32), PacketError> {
+        self.0
+            .get(index)
+            .and_then(|v| v.as_vec2())
+            .ok_or(PacketError::InvalidVariant(index))
+    }
+
+    fn vec3(&self, index: usize) -> Result<(f32, f32, f32), PacketError> {
+        self.0
+            .get(index)
+            .and_then(|v| v.as_vec3())
+            .ok_or(PacketError::InvalidVariant(index))
+    }
+}
+
+fn net_id(data: &HashMap<String, String>) -> Option<u32> {
+    data.get("netID").and_then(|net_id| net_id.parse().ok())
+}
@@ -0,0 +1,12 @@
+use std::time::Instant;
+
+use super::bot_state::BotState;
+
+// What a bot was doing when one of its threads panicked
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub message: String,
+    pub state: BotState,
+    pub recent_packets: Vec<Vec<u8>>,
+    pub crashed_at: Instant,
+}
@@ -1,4 +1,4 @@
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[repr(u32)]
 pub enum EPacketType {
     NetMessageUnknown = 0,
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+// Base URLs of everything the login flow talks to, paths are appended by the login code
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub server_data: String,
+    pub login: String,
+    pub ubisoft: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            server_data: "https://www.growtopia1.com/growtopia/server_data.php".to_string(),
+            login: "https://login.growtopiagame.com".to_string(),
+            ubisoft: "https://public-ubiservices.ubi.com".to_string(),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::utils::random::{random_hex, random_mac_address};
 
+use super::{packet_error::PacketError, text_packet::TextPacket};
+
 #[derive(Debug, Default)]
 pub struct LoginInfo {
     pub uuid: String,
@@ -64,3 +66,126 @@ impl LoginInfo {
         }
     }
 }
+
+impl LoginInfo {
+    fn fields(&self) -> [(&'static str, &String); 27] {
+        [
+            ("UUIDToken", &self.uuid),
+            ("protocol", &self.protocol),
+            ("fhash", &self.fhash),
+            ("mac", &self.mac),
+            ("requestedName", &self.requested_name),
+            ("hash2", &self.hash2),
+            ("fz", &self.fz),
+            ("f", &self.f),
+            ("player_age", &self.player_age),
+            ("game_version", &self.game_version),
+            ("lmode", &self.lmode),
+            ("cbits", &self.cbits),
+            ("rid", &self.rid),
+            ("GDPR", &self.gdpr),
+            ("hash", &self.hash),
+            ("category", &self.category),
+            ("token", &self.token),
+            ("total_playtime", &self.total_playtime),
+            ("door_id", &self.door_id),
+            ("klv", &self.klv),
+            ("meta", &self.meta),
+            ("platformID", &self.platform_id),
+            ("deviceVersion", &self.device_version),
+            ("zf", &self.zf),
+            ("country", &self.country),
+            ("user", &self.user),
+            ("wk", &self.wk),
+        ]
+    }
+
+    fn fields_mut(&mut self) -> [(&'static str, &mut String); 27] {
+        [
+            ("UUIDToken", &mut self.uuid),
+            ("protocol", &mut self.protocol),
+            ("fhash", &mut self.fhash),
+            ("mac", &mut self.mac),
+            ("requestedName", &mut self.requested_name),
+            ("hash2", &mut self.hash2),
+            ("fz", &mut self.fz),
+            ("f", &mut self.f),
+            ("player_age", &mut self.player_age),
+            ("game_version", &mut self.game_version),
+            ("lmode", &mut self.lmode),
+            ("cbits", &mut self.cbits),
+            ("rid", &mut self.rid),
+            ("GDPR", &mut self.gdpr),
+            ("hash", &mut self.hash),
+            ("category", &mut self.category),
+            ("token", &mut self.token),
+            ("total_playtime", &mut self.total_playtime),
+            ("door_id", &mut self.door_id),
+            ("klv", &mut self.klv),
+            ("meta", &mut self.meta),
+            ("platformID", &mut self.platform_id),
+            ("deviceVersion", &mut self.device_version),
+            ("zf", &mut self.zf),
+            ("country", &mut self.country),
+            ("user", &mut self.user),
+            ("wk", &mut self.wk),
+        ]
+    }
+}
+
+impl From<&LoginInfo> for TextPacket {
+    fn from(login_info: &LoginInfo) -> Self {
+        let mut packet = TextPacket::new();
+        for (key, value) in login_info.fields() {
+            packet.insert(key, value);
+        }
+        packet
+    }
+}
+
+impl TryFrom<&TextPacket> for LoginInfo {
+    type Error = PacketError;
+
+    fn try_from(packet: &TextPacket) -> Result<Self, Self::Error> {
+        let mut login_info = LoginInfo::default();
+        for (key, value) in login_info.fields_mut() {
+            *value = packet.require(key)?.to_string();
+        }
+        Ok(login_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_a_text_packet() {
+        let mut login_info = LoginInfo::new();
+        login_info.uuid = "uuid-1".to_string();
+        login_info.token = "1234".to_string();
+        login_info.door_id = "door".to_string();
+
+        let packet = TextPacket::from(&login_info);
+        let data = packet.serialize();
+        assert!(
+            data.starts_with("UUIDToken|uuid-1\nprotocol|209\n"),
+            "{}",
+            data
+        );
+
+        let parsed = LoginInfo::try_from(&TextPacket::deserialize(&data)).unwrap();
+        assert_eq!(parsed.fields(), login_info.fields());
+    }
+
+    #[test]
+    fn missing_key_is_an_error() {
+        let mut packet = TextPacket::from(&LoginInfo::new());
+        packet = TextPacket::deserialize(&packet.serialize().replace("klv|", "klv_gone|"));
+
+        match LoginInfo::try_from(&packet) {
+            Err(PacketError::MissingKey(key)) => assert_eq!(key, "klv"),
+            other => panic!("expected MissingKey, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,6 +1,16 @@
 pub mod bot_info;
+pub mod bot_state;
+pub mod call_function;
+pub mod crash_report;
 pub mod e_login_method;
 pub mod e_packet_type;
 pub mod e_tank_packet_type;
+pub mod endpoints;
 pub mod login_info;
+pub mod packet_error;
+pub mod player;
+pub mod proxy;
+pub mod reconnect_policy;
 pub mod tank_packet_type;
+pub mod text_packet;
+pub mod tile_update;
@@ -0,0 +1,36 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PacketError {
+    TooShort { expected: usize, actual: usize },
+    InvalidVariant(usize),
+    MissingKey(String),
+    Malformed(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketError::TooShort { expected, actual } => write!(
+                f,
+                "packet too short: expected at least {} bytes, got {}",
+                expected, actual
+            ),
+            PacketError::InvalidVariant(index) => {
+                write!(f, "variant {} is missing or has the wrong type", index)
+            }
+            PacketError::MissingKey(key) => write!(f, "missing key: {}", key),
+            PacketError::Malformed(message) => write!(f, "malformed packet: {}", message),
+            PacketError::Io(err) => write!(f, "failed to read packet: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PacketError {}
+
+impl From<std::io::Error> for PacketError {
+    fn from(err: std::io::Error) -> Self {
+        PacketError::Io(err)
+    }
+}
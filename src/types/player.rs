@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::utils::text_parse;
+
+use super::bot_info::Position;
+
+// Someone else in the world the bot is in, as announced by OnSpawn
+#[derive(Debug, Default, Clone)]
+pub struct Player {
+    pub net_id: u32,
+    pub user_id: u32,
+    // Without color codes
+    pub name: String,
+    // In pixels, like the bot's own position
+    pub position: Position,
+    pub country: String,
+    pub is_mod: bool,
+    pub invisible: bool,
+}
+
+impl Player {
+    pub fn from_spawn(net_id: u32, data: &HashMap<String, String>) -> Player {
+        let flag = |key: &str| data.get(key).is_some_and(|value| value == "1");
+        let mut position = Position::default();
+        if let Some((x, y)) = data.get("posXY").and_then(|pos| pos.split_once('|')) {
+            position.x = x.parse().unwrap_or_default();
+            position.y = y.parse().unwrap_or_default();
+        }
+
+        Player {
+            net_id,
+            user_id: data
+                .get("userID")
+                .and_then(|user_id| user_id.parse().ok())
+                .unwrap_or_default(),
+            name: text_parse::strip_color_codes(data.get("name").map_or("", |name| name)),
+            position,
+            country: data.get("country").cloned().unwrap_or_default(),
+            is_mod: flag("mstate") || flag("smstate"),
+            invisible: flag("invis"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // As the server sends it for someone else joining the world
+    const SPAWN: &str = "spawn|avatar\nnetID|4\nuserID|123456\neid|123456|abcdef|0a0b0c\nip|-1\ncolrect|0|0|20|30\nposXY|1472|736\nname|``@Mod`5Name``\ntitleIcon|{}\ncountry|us\ninvis|1\nmstate|1\nsmstate|0\nonlineID|\n";
+
+    #[test]
+    fn parses_a_spawned_player() {
+        let data = text_parse::parse_and_store_as_map(SPAWN);
+        let player = Player::from_spawn(4, &data);
+
+        assert_eq!(player.net_id, 4);
+        assert_eq!(player.user_id, 123456);
+        assert_eq!(player.name, "@ModName");
+        assert_eq!((player.position.x, player.position.y), (1472.0, 736.0));
+        assert_eq!(player.country, "us");
+        assert!(player.is_mod);
+        assert!(player.invisible);
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_defaults() {
+        let data = text_parse::parse_and_store_as_map(
+            "spawn|avatar\nnetID|5\nposXY|oops\nname|`2Guest\nmstate|0\n",
+        );
+        let player = Player::from_spawn(5, &data);
+
+        assert_eq!(player.user_id, 0);
+        assert_eq!(player.name, "Guest");
+        assert_eq!((player.position.x, player.position.y), (0.0, 0.0));
+        assert!(player.country.is_empty());
+        assert!(!player.is_mod);
+        assert!(!player.invisible);
+    }
+
+    #[test]
+    fn super_mod_counts_as_mod() {
+        let data = text_parse::parse_and_store_as_map("netID|6\nmstate|0\nsmstate|1\n");
+        assert!(Player::from_spawn(6, &data).is_mod);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    ServerFull,
+    Maintenance,
+    BadCredentials,
+    Banned,
+    NetworkError,
+}
+
+impl DisconnectReason {
+    // Classifies the text the server sends along with logon_fail
+    pub fn classify(message: &str) -> Option<DisconnectReason> {
+        let message = message.to_lowercase();
+        if message.contains("currently banned") || message.contains("suspended") {
+            Some(DisconnectReason::Banned)
+        } else if message.contains("maintenance") {
+            Some(DisconnectReason::Maintenance)
+        } else if message.contains("too many people") || message.contains("server is full") {
+            Some(DisconnectReason::ServerFull)
+        } else if message.contains("doesn't seem valid")
+            || message.contains("password is wrong")
+            || message.contains("incorrect password")
+            || message.contains("wrong password")
+        {
+            Some(DisconnectReason::BadCredentials)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DisconnectReason::ServerFull => "Server full",
+            DisconnectReason::Maintenance => "Maintenance",
+            DisconnectReason::BadCredentials => "Bad credentials",
+            DisconnectReason::Banned => "Banned",
+            DisconnectReason::NetworkError => "Network error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub maintenance_delay_ms: u64,
+    // Fraction of the delay that is randomly added or removed
+    pub jitter: f64,
+    // 0 means retry forever
+    pub max_retries: u32,
+    pub retry_bad_credentials: bool,
+    // Restart the bot after base_delay_ms when one of its threads panics
+    pub restart_on_crash: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            base_delay_ms: 2_000,
+            max_delay_ms: 120_000,
+            maintenance_delay_ms: 300_000,
+            jitter: 0.2,
+            max_retries: 10,
+            retry_bad_credentials: false,
+            restart_on_crash: false,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    // Returns None when the bot should give up instead of reconnecting
+    pub fn delay(&self, reason: DisconnectReason, attempt: u32) -> Option<Duration> {
+        if self.max_retries != 0 && attempt >= self.max_retries {
+            return None;
+        }
+
+        let delay_ms = match reason {
+            DisconnectReason::Banned => return None,
+            DisconnectReason::BadCredentials if !self.retry_bad_credentials => return None,
+            DisconnectReason::Maintenance => self.maintenance_delay_ms,
+            DisconnectReason::ServerFull
+            | DisconnectReason::BadCredentials
+            | DisconnectReason::NetworkError => self
+                .base_delay_ms
+                .saturating_mul(1u64 << attempt.min(16))
+                .min(self.max_delay_ms),
+        };
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+        Some(Duration::from_millis((delay_ms as f64 * factor) as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_banned() {
+        assert_eq!(
+            DisconnectReason::classify(
+                "action|log\nmsg|`4Sorry, this account is currently banned.``"
+            ),
+            Some(DisconnectReason::Banned)
+        );
+        assert_eq!(
+            DisconnectReason::classify("Sorry, this account (`5test``) has been suspended."),
+            Some(DisconnectReason::Banned)
+        );
+    }
+
+    #[test]
+    fn classifies_maintenance() {
+        assert_eq!(
+            DisconnectReason::classify("`4Server is currently down for MAINTENANCE``"),
+            Some(DisconnectReason::Maintenance)
+        );
+    }
+
+    #[test]
+    fn classifies_server_full() {
+        assert_eq!(
+            DisconnectReason::classify("`4OOPS:`` Too many people logging in at once."),
+            Some(DisconnectReason::ServerFull)
+        );
+        assert_eq!(
+            DisconnectReason::classify("The server is full, try again later."),
+            Some(DisconnectReason::ServerFull)
+        );
+    }
+
+    #[test]
+    fn classifies_bad_credentials() {
+        assert_eq!(
+            DisconnectReason::classify(
+                "`4Unable to log on:`` That `wGrowID`` doesn't seem valid, or the password is wrong."
+            ),
+            Some(DisconnectReason::BadCredentials)
+        );
+        assert_eq!(
+            DisconnectReason::classify("Incorrect password."),
+            Some(DisconnectReason::BadCredentials)
+        );
+    }
+
+    #[test]
+    fn ignores_other_mentions_of_password() {
+        assert_eq!(
+            DisconnectReason::classify("Remember to never share your password with anyone!"),
+            None
+        );
+        assert_eq!(
+            DisconnectReason::classify("Where would you like to go?"),
+            None
+        );
+    }
+}
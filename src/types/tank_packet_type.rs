@@ -1,5 +1,10 @@
+use std::io::{Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
 use super::e_tank_packet_type::ETankPacketType;
 
+#[derive(Debug, Clone)]
 pub struct TankPacketType {
     pub packet_type: ETankPacketType,
     pub unk1: u8,
@@ -18,9 +23,12 @@ pub struct TankPacketType {
     pub int_x: i32,
     pub int_y: i32,
     pub extended_data_length: u32,
+    pub extended_data: Vec<u8>,
 }
 
 impl TankPacketType {
+    pub const HEADER_SIZE: usize = 56;
+
     pub fn new() -> TankPacketType {
         TankPacketType {
             packet_type: ETankPacketType::NetGamePacketCallFunction,
@@ -40,6 +48,203 @@ impl TankPacketType {
             int_x: 0,
             int_y: 0,
             extended_data_length: 0,
+            extended_data: Vec::new(),
+        }
+    }
+
+    // NetGamePacketModifyItemInventory reuses the header bytes after the packet type,
+    // value holds the item id
+    pub fn removed_amount(&self) -> u8 {
+        self.unk2
+    }
+
+    pub fn added_amount(&self) -> u8 {
+        self.unk3
+    }
+
+    // extended_data_length is always written from extended_data, the field is only kept for inspection
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::HEADER_SIZE + self.extended_data.len());
+        data.push(self.packet_type as u8);
+        data.push(self.unk1);
+        data.push(self.unk2);
+        data.push(self.unk3);
+        data.extend_from_slice(&self.net_id.to_le_bytes());
+        data.extend_from_slice(&self.unk4.to_le_bytes());
+        data.extend_from_slice(&self.flags.to_le_bytes());
+        data.extend_from_slice(&self.unk6.to_le_bytes());
+        data.extend_from_slice(&self.value.to_le_bytes());
+        data.extend_from_slice(&self.vector_x.to_le_bytes());
+        data.extend_from_slice(&self.vector_y.to_le_bytes());
+        data.extend_from_slice(&self.vector_x2.to_le_bytes());
+        data.extend_from_slice(&self.vector_y2.to_le_bytes());
+        data.extend_from_slice(&self.unk12.to_le_bytes());
+        data.extend_from_slice(&self.int_x.to_le_bytes());
+        data.extend_from_slice(&self.int_y.to_le_bytes());
+        data.extend_from_slice(&(self.extended_data.len() as u32).to_le_bytes());
+        data.extend_from_slice(&self.extended_data);
+        data
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, std::io::Error> {
+        let mut cursor = Cursor::new(data);
+        let mut pkt = TankPacketType {
+            packet_type: ETankPacketType::from(cursor.read_u8()?),
+            unk1: cursor.read_u8()?,
+            unk2: cursor.read_u8()?,
+            unk3: cursor.read_u8()?,
+            net_id: cursor.read_u32::<LittleEndian>()?,
+            unk4: cursor.read_u32::<LittleEndian>()?,
+            flags: cursor.read_u32::<LittleEndian>()?,
+            unk6: cursor.read_u32::<LittleEndian>()?,
+            value: cursor.read_u32::<LittleEndian>()?,
+            vector_x: cursor.read_f32::<LittleEndian>()?,
+            vector_y: cursor.read_f32::<LittleEndian>()?,
+            vector_x2: cursor.read_f32::<LittleEndian>()?,
+            vector_y2: cursor.read_f32::<LittleEndian>()?,
+            unk12: cursor.read_f32::<LittleEndian>()?,
+            int_x: cursor.read_i32::<LittleEndian>()?,
+            int_y: cursor.read_i32::<LittleEndian>()?,
+            extended_data_length: cursor.read_u32::<LittleEndian>()?,
+            extended_data: Vec::new(),
+        };
+
+        let remaining = data.len() - cursor.position() as usize;
+        if (pkt.extended_data_length as usize) > remaining {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "extended data is shorter than extended_data_length",
+            ));
+        }
+        let mut extended_data = vec![0; pkt.extended_data_length as usize];
+        cursor.read_exact(&mut extended_data)?;
+        pkt.extended_data = extended_data;
+        Ok(pkt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> TankPacketType {
+        TankPacketType {
+            packet_type: ETankPacketType::NetGamePacketState,
+            unk1: 1,
+            unk2: 2,
+            unk3: 3,
+            net_id: 42,
+            unk4: 7,
+            flags: 0x20,
+            unk6: 9,
+            value: 1000,
+            vector_x: 32.0,
+            vector_y: 64.5,
+            vector_x2: -1.0,
+            vector_y2: 2.5,
+            unk12: 0.25,
+            int_x: -1,
+            int_y: 12,
+            extended_data_length: 0,
+            extended_data: Vec::new(),
+        }
+    }
+
+    fn assert_header_eq(a: &TankPacketType, b: &TankPacketType) {
+        assert_eq!(a.packet_type, b.packet_type);
+        assert_eq!((a.unk1, a.unk2, a.unk3), (b.unk1, b.unk2, b.unk3));
+        assert_eq!(
+            (a.net_id, a.unk4, a.flags, a.unk6, a.value),
+            (b.net_id, b.unk4, b.flags, b.unk6, b.value)
+        );
+        assert_eq!((a.vector_x, a.vector_y), (b.vector_x, b.vector_y));
+        assert_eq!(
+            (a.vector_x2, a.vector_y2, a.unk12),
+            (b.vector_x2, b.vector_y2, b.unk12)
+        );
+        assert_eq!((a.int_x, a.int_y), (b.int_x, b.int_y));
+    }
+
+    #[test]
+    fn round_trips_header_without_extended_data() {
+        let pkt = sample();
+        let data = pkt.serialize();
+        assert_eq!(data.len(), TankPacketType::HEADER_SIZE);
+
+        let parsed = TankPacketType::deserialize(&data).unwrap();
+        assert_header_eq(&pkt, &parsed);
+        assert_eq!(parsed.extended_data_length, 0);
+        assert!(parsed.extended_data.is_empty());
+    }
+
+    #[test]
+    fn round_trips_header_with_extended_data() {
+        let mut pkt = sample();
+        pkt.extended_data = vec![1, 2, 3, 4, 5];
+        let data = pkt.serialize();
+        assert_eq!(data.len(), TankPacketType::HEADER_SIZE + 5);
+
+        let parsed = TankPacketType::deserialize(&data).unwrap();
+        assert_header_eq(&pkt, &parsed);
+        assert_eq!(parsed.extended_data_length, 5);
+        assert_eq!(parsed.extended_data, pkt.extended_data);
+    }
+
+    #[test]
+    fn serialize_writes_length_from_extended_data() {
+        let mut pkt = sample();
+        pkt.extended_data = vec![0xAA; 3];
+        pkt.extended_data_length = 100;
+
+        let parsed = TankPacketType::deserialize(&pkt.serialize()).unwrap();
+        assert_eq!(parsed.extended_data_length, 3);
+        assert_eq!(parsed.extended_data, vec![0xAA; 3]);
+    }
+
+    #[test]
+    fn rejects_length_longer_than_remaining_bytes() {
+        let mut pkt = sample();
+        pkt.extended_data = vec![1, 2, 3, 4];
+        let mut data = pkt.serialize();
+        data.truncate(data.len() - 2);
+
+        let err = TankPacketType::deserialize(&data).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn ignores_bytes_past_extended_data_length() {
+        let mut pkt = sample();
+        pkt.extended_data = vec![1, 2];
+        let mut data = pkt.serialize();
+        data.extend_from_slice(&[9, 9, 9]);
+
+        let parsed = TankPacketType::deserialize(&data).unwrap();
+        assert_eq!(parsed.extended_data_length, 2);
+        assert_eq!(parsed.extended_data, vec![1, 2]);
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let data = sample().serialize();
+        for len in 0..TankPacketType::HEADER_SIZE {
+            let err = TankPacketType::deserialize(&data[..len]).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                std::io::ErrorKind::UnexpectedEof,
+                "truncated to {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_extended_data() {
+        let mut pkt = sample();
+        pkt.extended_data = vec![1, 2, 3, 4];
+        let data = pkt.serialize();
+        for len in TankPacketType::HEADER_SIZE..data.len() {
+            assert!(TankPacketType::deserialize(&data[..len]).is_err());
         }
     }
 }
@@ -0,0 +1,133 @@
+use super::packet_error::PacketError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextPacket {
+    entries: Vec<(String, String)>,
+}
+
+impl TextPacket {
+    pub fn new() -> TextPacket {
+        TextPacket {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn action(action: &str) -> TextPacket {
+        TextPacket::new().with("action", action)
+    }
+
+    pub fn with(mut self, key: &str, value: impl ToString) -> TextPacket {
+        self.insert(key, value);
+        self
+    }
+
+    // Keeps the original position of the key when it's already present
+    pub fn insert(&mut self, key: &str, value: impl ToString) {
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn require(&self, key: &str) -> Result<&str, PacketError> {
+        self.get(key)
+            .ok_or(PacketError::MissingKey(key.to_string()))
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut data = String::new();
+        for (key, value) in &self.entries {
+            data.push_str(key);
+            data.push('|');
+            data.push_str(value);
+            data.push('\n');
+        }
+        data
+    }
+
+    pub fn deserialize(data: &str) -> TextPacket {
+        let mut packet = TextPacket::new();
+        for line in data.lines() {
+            if let Some((key, value)) = line.split_once('|') {
+                packet
+                    .entries
+                    .push((key.to_string(), value.trim_end_matches('\0').to_string()));
+            }
+        }
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_entries_in_order() {
+        let packet = TextPacket::action("join_request")
+            .with("name", "START")
+            .with("invitedWorld", 0);
+        let data = packet.serialize();
+        assert_eq!(data, "action|join_request\nname|START\ninvitedWorld|0\n");
+        assert_eq!(TextPacket::deserialize(&data), packet);
+    }
+
+    #[test]
+    fn trims_the_trailing_nul() {
+        let packet = TextPacket::deserialize("action|log\nmsg|hello\0");
+        assert_eq!(packet.get("msg"), Some("hello"));
+    }
+
+    #[test]
+    fn keeps_an_empty_key() {
+        // talk sends the message under an empty key
+        let packet = TextPacket::action("input").with("", "text|hi");
+        let data = packet.serialize();
+        assert_eq!(data, "action|input\n|text|hi\n");
+
+        let parsed = TextPacket::deserialize(&data);
+        assert_eq!(parsed.get(""), Some("text|hi"));
+        assert_eq!(parsed, packet);
+    }
+
+    #[test]
+    fn skips_lines_without_a_separator() {
+        let packet = TextPacket::deserialize("action|log\ngarbage\n\nmsg|x|y");
+        assert_eq!(
+            packet.entries(),
+            &[
+                ("action".to_string(), "log".to_string()),
+                ("msg".to_string(), "x|y".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_keeps_the_position_of_an_existing_key() {
+        let mut packet = TextPacket::new().with("a", 1).with("b", 2).with("c", 3);
+        packet.insert("a", 10);
+        packet.insert("d", 4);
+        assert_eq!(packet.serialize(), "a|10\nb|2\nc|3\nd|4\n");
+    }
+
+    #[test]
+    fn require_reports_the_missing_key() {
+        let packet = TextPacket::action("log");
+        assert_eq!(packet.require("action").unwrap(), "log");
+        match packet.require("msg") {
+            Err(PacketError::MissingKey(key)) => assert_eq!(key, "msg"),
+            other => panic!("expected MissingKey, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::packet_error::PacketError;
+use super::tank_packet_type::TankPacketType;
+
+// Item id of the fist, a tile change with it means the tile was broken
+pub const FIST: u16 = 18;
+// Set when the tile is followed by extra data (signs, locks, trees...)
+const HAS_EXTRA_DATA: u16 = 0x1;
+const TILE_SIZE: usize = 8;
+
+// The fixed part of a tile, the same layout as in the map data
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileUpdate {
+    pub x: u32,
+    pub y: u32,
+    pub foreground_item_id: u16,
+    pub background_item_id: u16,
+    pub parent_block_index: u16,
+    pub flags: u16,
+}
+
+impl TileUpdate {
+    // NetGamePacketSendTileUpdateData, the position is in the header
+    pub fn from_packet(pkt: &TankPacketType) -> Result<TileUpdate, PacketError> {
+        let x = u32::try_from(pkt.int_x)
+            .map_err(|_| PacketError::Malformed(format!("tile x {}", pkt.int_x)))?;
+        let y = u32::try_from(pkt.int_y)
+            .map_err(|_| PacketError::Malformed(format!("tile y {}", pkt.int_y)))?;
+        TileUpdate::parse(x, y, &pkt.extended_data)
+    }
+
+    // NetGamePacketSendTileUpdateDataMultiple, a position before each tile and -1, -1 at the end
+    pub fn parse_multiple(data: &[u8]) -> Result<Vec<TileUpdate>, PacketError> {
+        let mut updates = Vec::new();
+        let mut rest = data;
+        while rest.len() >= 8 {
+            let x = LittleEndian::read_i32(&rest[0..4]);
+            let y = LittleEndian::read_i32(&rest[4..8]);
+            if x < 0 || y < 0 {
+                break;
+            }
+            let update = TileUpdate::parse(x as u32, y as u32, &rest[8..])?;
+            // The length of extra data depends on the item, so nothing after it can be found
+            let has_extra_data = update.flags & HAS_EXTRA_DATA != 0;
+            updates.push(update);
+            if has_extra_data {
+                break;
+            }
+            rest = &rest[8 + TILE_SIZE..];
+        }
+        Ok(updates)
+    }
+
+    fn parse(x: u32, y: u32, data: &[u8]) -> Result<TileUpdate, PacketError> {
+        if data.len() < TILE_SIZE {
+            return Err(PacketError::TooShort {
+                expected: TILE_SIZE,
+                actual: data.len(),
+            });
+        }
+        Ok(TileUpdate {
+            x,
+            y,
+            foreground_item_id: LittleEndian::read_u16(&data[0..2]),
+            background_item_id: LittleEndian::read_u16(&data[2..4]),
+            parent_block_index: LittleEndian::read_u16(&data[4..6]),
+            flags: LittleEndian::read_u16(&data[6..8]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(x: i32, y: i32, foreground: u16, flags: u16) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&x.to_le_bytes());
+        data.extend_from_slice(&y.to_le_bytes());
+        data.extend_from_slice(&foreground.to_le_bytes());
+        data.extend_from_slice(&14u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data
+    }
+
+    fn terminator() -> Vec<u8> {
+        [(-1i32).to_le_bytes(), (-1i32).to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn parses_until_the_terminator() {
+        let mut data = [tile(1, 2, 2, 0), tile(3, 4, 0, 0)].concat();
+        data.extend_from_slice(&terminator());
+        // Whatever follows the terminator isn't a tile
+        data.extend_from_slice(&tile(5, 6, 2, 0));
+
+        let updates = TileUpdate::parse_multiple(&data).unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!((updates[0].x, updates[0].y), (1, 2));
+        assert_eq!(updates[0].foreground_item_id, 2);
+        assert_eq!(updates[0].background_item_id, 14);
+        assert_eq!((updates[1].x, updates[1].y), (3, 4));
+    }
+
+    #[test]
+    fn stops_after_a_tile_with_extra_data() {
+        let mut data = tile(1, 2, 20, HAS_EXTRA_DATA);
+        // A sign's text, its length can't be known without the item
+        data.extend_from_slice(&[1, 5, 0, b'h', b'e', b'l', b'l', b'o']);
+        data.extend_from_slice(&tile(3, 4, 2, 0));
+
+        let updates = TileUpdate::parse_multiple(&data).unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].foreground_item_id, 20);
+    }
+
+    #[test]
+    fn negative_coordinates_end_the_list() {
+        let data = [tile(1, 2, 2, 0), tile(-1, 4, 2, 0), tile(3, 4, 2, 0)].concat();
+        assert_eq!(TileUpdate::parse_multiple(&data).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn short_tile_is_an_error() {
+        let data = tile(1, 2, 2, 0);
+        for len in 8..data.len() {
+            assert!(
+                matches!(
+                    TileUpdate::parse_multiple(&data[..len]),
+                    Err(PacketError::TooShort { expected: 8, .. })
+                ),
+                "truncated to {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn short_position_ends_the_list() {
+        assert!(TileUpdate::parse_multiple(&[]).unwrap().is_empty());
+        let mut data = tile(1, 2, 2, 0);
+        data.extend_from_slice(&[0, 0, 0]);
+        assert_eq!(TileUpdate::parse_multiple(&data).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn from_packet_takes_the_position_from_the_header() {
+        let mut pkt = TankPacketType::new();
+        pkt.int_x = 7;
+        pkt.int_y = 9;
+        pkt.extended_data = tile(0, 0, 2, 0)[8..].to_vec();
+
+        let update = TileUpdate::from_packet(&pkt).unwrap();
+        assert_eq!((update.x, update.y), (7, 9));
+        assert_eq!(update.foreground_item_id, 2);
+    }
+
+    #[test]
+    fn from_packet_rejects_negative_positions_and_short_data() {
+        let mut pkt = TankPacketType::new();
+        pkt.int_x = -1;
+        pkt.extended_data = tile(0, 0, 2, 0)[8..].to_vec();
+        assert!(matches!(
+            TileUpdate::from_packet(&pkt),
+            Err(PacketError::Malformed(_))
+        ));
+
+        pkt.int_x = 0;
+        pkt.extended_data.truncate(TILE_SIZE - 1);
+        assert!(matches!(
+            TileUpdate::from_packet(&pkt),
+            Err(PacketError::TooShort { .. })
+        ));
+    }
+}
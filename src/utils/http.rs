@@ -0,0 +1,155 @@
+use std::fmt;
+
+use ureq::Agent;
+
+use crate::types::proxy::Proxy;
+
+// The login code only talks HTTP through this trait, so it can be pointed at a mock server
+// or replaced entirely without touching the flows themselves
+pub trait HttpClient: Send + Sync {
+    // Returns the body of a 2xx response, anything else is an error
+    fn send(&self, request: Request) -> Result<String, HttpError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    Empty,
+    Text(String),
+    Form(Vec<(String, String)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Request {
+    pub fn get(url: impl Into<String>) -> Request {
+        Request {
+            method: Method::Get,
+            url: url.into(),
+            headers: Vec::new(),
+            body: Body::Empty,
+        }
+    }
+
+    pub fn post(url: impl Into<String>) -> Request {
+        Request {
+            method: Method::Post,
+            ..Request::get(url)
+        }
+    }
+
+    pub fn header(mut self, key: &str, value: &str) -> Request {
+        self.headers.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn text(mut self, body: impl Into<String>) -> Request {
+        self.body = Body::Text(body.into());
+        self
+    }
+
+    pub fn form(mut self, fields: &[(&str, &str)]) -> Request {
+        self.body = Body::Form(
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    Status(u16, String),
+    Transport(String),
+    // A 2xx response that didn't hold what the login flow was looking for
+    InvalidResponse(String),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Status(code, body) => write!(f, "status {}: {}", code, body),
+            HttpError::Transport(message) => write!(f, "{}", message),
+            HttpError::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<ureq::Error> for HttpError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Status(code, response) => {
+                HttpError::Status(code, response.into_string().unwrap_or_default())
+            }
+            ureq::Error::Transport(transport) => HttpError::Transport(transport.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for HttpError {
+    fn from(err: std::io::Error) -> Self {
+        HttpError::Transport(err.to_string())
+    }
+}
+
+// The agent keeps cookies between requests, the legacy login needs its session cookie back
+pub struct UreqClient {
+    // A proxy ureq can't use fails every request instead of silently going direct
+    agent: Result<Agent, String>,
+}
+
+impl UreqClient {
+    pub fn new(proxy: Option<&Proxy>) -> UreqClient {
+        let agent = match proxy {
+            Some(proxy) => ureq::Proxy::new(proxy.url())
+                .map(|ureq_proxy| ureq::AgentBuilder::new().proxy(ureq_proxy).build())
+                .map_err(|err| format!("invalid proxy {}: {}", proxy, err)),
+            None => Ok(Agent::new()),
+        };
+        UreqClient { agent }
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn send(&self, request: Request) -> Result<String, HttpError> {
+        let agent = self
+            .agent
+            .as_ref()
+            .map_err(|err| HttpError::Transport(err.clone()))?;
+        let mut req = match request.method {
+            Method::Get => agent.get(&request.url),
+            Method::Post => agent.post(&request.url),
+        };
+        for (key, value) in &request.headers {
+            req = req.set(key, value);
+        }
+        let response = match &request.body {
+            Body::Empty if request.method == Method::Get => req.call()?,
+            Body::Empty => req.send_string("")?,
+            Body::Text(body) => req.send_string(body)?,
+            Body::Form(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+                    .collect::<Vec<_>>();
+                req.send_form(&fields)?
+            }
+        };
+        Ok(response.into_string()?)
+    }
+}
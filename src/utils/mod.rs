@@ -1,5 +1,6 @@
-pub mod bytes;
+pub mod http;
 pub mod proton;
 pub mod random;
+pub mod socks5;
 pub mod text_parse;
 pub mod variant;
@@ -0,0 +1,399 @@
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+use spdlog::warn;
+
+use crate::types::proxy::Proxy;
+
+const VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_USER_PASS: u8 = 2;
+const COMMAND_UDP_ASSOCIATE: u8 = 3;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+const UDP_HEADER_SIZE: usize = 10;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// How often the relay thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// ENet owns its socket, so it can't speak SOCKS5 itself. It sends to a local UDP socket instead,
+// and this relay wraps each datagram for the proxy's UDP ASSOCIATE relay and unwraps the replies.
+// The relay only talks to a single target, a redirect needs a new one.
+pub struct UdpRelay {
+    local_addr: SocketAddrV4,
+    target: SocketAddrV4,
+    stop: Arc<AtomicBool>,
+}
+
+impl UdpRelay {
+    pub fn open(proxy: &Proxy, target: SocketAddrV4) -> io::Result<UdpRelay> {
+        let proxy_addr = (proxy.host.as_str(), proxy.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "proxy host not found"))?;
+        let mut control = TcpStream::connect_timeout(&proxy_addr, HANDSHAKE_TIMEOUT)?;
+        control.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        control.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        authenticate(&mut control, proxy)?;
+        let mut relay_addr = udp_associate(&mut control)?;
+        // Some servers answer with an unspecified address, meaning "the host you talked to"
+        if relay_addr.ip().is_unspecified() {
+            relay_addr.set_ip(proxy_addr.ip());
+        }
+
+        let local = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let remote = UdpSocket::bind(match relay_addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+        })?;
+        remote.connect(relay_addr)?;
+        local.set_read_timeout(Some(POLL_INTERVAL))?;
+        remote.set_read_timeout(Some(POLL_INTERVAL))?;
+        control.set_read_timeout(None)?;
+
+        let local_addr = match local.local_addr()? {
+            SocketAddr::V4(addr) => addr,
+            SocketAddr::V6(_) => unreachable!("bound to an IPv4 address"),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = Arc::clone(&stop);
+        spawn(move || {
+            // The association only lives as long as the control connection
+            let _control = control;
+            relay(local, remote, target, stop_clone);
+        });
+
+        Ok(UdpRelay {
+            local_addr,
+            target,
+            stop,
+        })
+    }
+
+    // Where ENet should connect to instead of the target
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.local_addr
+    }
+
+    pub fn target(&self) -> SocketAddrV4 {
+        self.target
+    }
+}
+
+impl Drop for UdpRelay {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn authenticate(stream: &mut TcpStream, proxy: &Proxy) -> io::Result<()> {
+    if proxy.has_auth() {
+        stream.write_all(&[VERSION, 2, METHOD_NO_AUTH, METHOD_USER_PASS])?;
+    } else {
+        stream.write_all(&[VERSION, 1, METHOD_NO_AUTH])?;
+    }
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("not a SOCKS5 proxy"));
+    }
+
+    match reply[1] {
+        METHOD_NO_AUTH => Ok(()),
+        METHOD_USER_PASS if proxy.has_auth() => {
+            let username = proxy.username.as_bytes();
+            let password = proxy.password.as_bytes();
+            if username.len() > 255 || password.len() > 255 {
+                return Err(invalid_data("proxy credentials are too long"));
+            }
+            let mut request = vec![1, username.len() as u8];
+            request.extend_from_slice(username);
+            request.push(password.len() as u8);
+            request.extend_from_slice(password);
+            stream.write_all(&request)?;
+
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "proxy rejected the credentials",
+                ));
+            }
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "proxy accepts none of the offered auth methods",
+        )),
+    }
+}
+
+fn udp_associate(stream: &mut TcpStream) -> io::Result<SocketAddr> {
+    // The address we'll send from isn't known yet, all zeroes lets the proxy take it from the first datagram
+    stream.write_all(&[
+        VERSION,
+        COMMAND_UDP_ASSOCIATE,
+        0,
+        ATYP_IPV4,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ])?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("proxy refused UDP ASSOCIATE (reply {})", reply[1]),
+        ));
+    }
+    read_address(stream, reply[3])
+}
+
+fn read_address(stream: &mut TcpStream, atyp: u8) -> io::Result<SocketAddr> {
+    let ip = match atyp {
+        ATYP_IPV4 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip)?;
+            std::net::IpAddr::from(ip)
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0u8; 16];
+            stream.read_exact(&mut ip)?;
+            std::net::IpAddr::from(ip)
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            let mut domain = vec![0u8; len[0] as usize];
+            stream.read_exact(&mut domain)?;
+            let mut port = [0u8; 2];
+            stream.read_exact(&mut port)?;
+            let domain = String::from_utf8_lossy(&domain).to_string();
+            return (domain.as_str(), u16::from_be_bytes(port))
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| invalid_data("proxy relay host not found"));
+        }
+        _ => return Err(invalid_data("unknown address type")),
+    };
+    let mut port = [0u8; 2];
+    stream.read_exact(&mut port)?;
+    Ok(SocketAddr::new(ip, u16::from_be_bytes(port)))
+}
+
+fn relay(local: UdpSocket, remote: UdpSocket, target: SocketAddrV4, stop: Arc<AtomicBool>) {
+    let client: Arc<Mutex<Option<SocketAddr>>> = Arc::new(Mutex::new(None));
+    let (local_clone, remote_clone) = match (local.try_clone(), remote.try_clone()) {
+        (Ok(local), Ok(remote)) => (local, remote),
+        _ => {
+            warn!("Failed to clone the proxy relay sockets");
+            return;
+        }
+    };
+    let client_clone = Arc::clone(&client);
+    let stop_clone = Arc::clone(&stop);
+    let replies = spawn(move || {
+        let mut buffer = [0u8; 65536];
+        while !stop_clone.load(Ordering::Relaxed) {
+            match remote_clone.recv(&mut buffer) {
+                Ok(size) => {
+                    // Fragmented datagrams aren't supported, they're dropped like the RFC allows
+                    if size < UDP_HEADER_SIZE || buffer[2] != 0 || buffer[3] != ATYP_IPV4 {
+                        continue;
+                    }
+                    if let Some(client) = *client_clone.lock().unwrap() {
+                        let _ = local_clone.send_to(&buffer[UDP_HEADER_SIZE..size], client);
+                    }
+                }
+                Err(err) if is_timeout(&err) => (),
+                Err(err) => {
+                    warn!("Proxy relay stopped: {}", err);
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut header = vec![0, 0, 0, ATYP_IPV4];
+    header.extend_from_slice(&target.ip().octets());
+    header.extend_from_slice(&target.port().to_be_bytes());
+    let mut buffer = [0u8; 65536];
+    while !stop.load(Ordering::Relaxed) {
+        match local.recv_from(&mut buffer) {
+            Ok((size, from)) => {
+                *client.lock().unwrap() = Some(from);
+                let mut datagram = header.clone();
+                datagram.extend_from_slice(&buffer[..size]);
+                if let Err(err) = remote.send(&datagram) {
+                    warn!("Failed to send to the proxy: {}", err);
+                }
+            }
+            Err(err) if is_timeout(&err) => (),
+            Err(err) => {
+                warn!("Proxy relay stopped: {}", err);
+                break;
+            }
+        }
+    }
+    stop.store(true, Ordering::Relaxed);
+    let _ = replies.join();
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::proxy::ProxyKind;
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    const TARGET: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 1, 2, 3), 17091);
+
+    // A SOCKS5 server that takes one UDP ASSOCIATE and echoes the first datagram back
+    fn stand_in(
+        credentials: Option<(&'static str, &'static str)>,
+        udp: bool,
+    ) -> (u16, JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = spawn(move || {
+            let (mut control, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 2];
+            control.read_exact(&mut greeting).unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            control.read_exact(&mut methods).unwrap();
+
+            match credentials {
+                Some((username, password)) => {
+                    assert!(methods.contains(&METHOD_USER_PASS));
+                    control.write_all(&[VERSION, METHOD_USER_PASS]).unwrap();
+                    let mut len = [0u8; 2];
+                    control.read_exact(&mut len).unwrap();
+                    let mut given_username = vec![0u8; len[1] as usize];
+                    control.read_exact(&mut given_username).unwrap();
+                    control.read_exact(&mut len[..1]).unwrap();
+                    let mut given_password = vec![0u8; len[0] as usize];
+                    control.read_exact(&mut given_password).unwrap();
+                    let accepted = given_username == username.as_bytes()
+                        && given_password == password.as_bytes();
+                    control
+                        .write_all(&[1, if accepted { 0 } else { 1 }])
+                        .unwrap();
+                    if !accepted {
+                        return Vec::new();
+                    }
+                }
+                None => control.write_all(&[VERSION, METHOD_NO_AUTH]).unwrap(),
+            }
+
+            let mut request = [0u8; 10];
+            control.read_exact(&mut request).unwrap();
+            assert_eq!(request[1], COMMAND_UDP_ASSOCIATE);
+            if !udp {
+                // Command not supported
+                control
+                    .write_all(&[VERSION, 7, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+                    .unwrap();
+                return Vec::new();
+            }
+
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            let mut reply = vec![VERSION, 0, 0, ATYP_IPV4];
+            reply.extend_from_slice(&Ipv4Addr::LOCALHOST.octets());
+            reply.extend_from_slice(&socket.local_addr().unwrap().port().to_be_bytes());
+            control.write_all(&reply).unwrap();
+
+            let mut buffer = [0u8; 1024];
+            socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).unwrap();
+            let (size, from) = socket.recv_from(&mut buffer).unwrap();
+            socket.send_to(&buffer[..size], from).unwrap();
+            buffer[..size].to_vec()
+        });
+        (port, handle)
+    }
+
+    fn proxy(port: u16, username: &str, password: &str) -> Proxy {
+        Proxy {
+            kind: ProxyKind::Socks5,
+            host: Ipv4Addr::LOCALHOST.to_string(),
+            port,
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    fn echo(relay: &UdpRelay, payload: &[u8]) -> Vec<u8> {
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).unwrap();
+        client.send_to(payload, relay.local_addr()).unwrap();
+        let mut buffer = [0u8; 1024];
+        let size = client.recv(&mut buffer).unwrap();
+        buffer[..size].to_vec()
+    }
+
+    fn wrapped(payload: &[u8]) -> Vec<u8> {
+        let mut datagram = vec![0, 0, 0, ATYP_IPV4];
+        datagram.extend_from_slice(&TARGET.ip().octets());
+        datagram.extend_from_slice(&TARGET.port().to_be_bytes());
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    #[test]
+    fn relays_datagrams_without_auth() {
+        let (port, server) = stand_in(None, true);
+        let relay = UdpRelay::open(&proxy(port, "", ""), TARGET).unwrap();
+        assert_eq!(relay.target(), TARGET);
+
+        assert_eq!(echo(&relay, b"hello"), b"hello");
+        assert_eq!(server.join().unwrap(), wrapped(b"hello"));
+    }
+
+    #[test]
+    fn relays_datagrams_with_credentials() {
+        let (port, server) = stand_in(Some(("user", "pass")), true);
+        let relay = UdpRelay::open(&proxy(port, "user", "pass"), TARGET).unwrap();
+
+        assert_eq!(echo(&relay, b"growtopia"), b"growtopia");
+        assert_eq!(server.join().unwrap(), wrapped(b"growtopia"));
+    }
+
+    #[test]
+    fn fails_on_rejected_credentials() {
+        let (port, server) = stand_in(Some(("user", "pass")), true);
+        let err = UdpRelay::open(&proxy(port, "user", "wrong"), TARGET)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn fails_when_udp_associate_is_refused() {
+        let (port, server) = stand_in(None, false);
+        let err = UdpRelay::open(&proxy(port, "", ""), TARGET).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        server.join().unwrap();
+    }
+}
@@ -16,3 +16,17 @@ pub fn parse_and_store_as_map(input: &str) -> HashMap<String, String> {
     }
     map
 }
+
+// Drops the `x color codes the game puts in names and messages
+pub fn strip_color_codes(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c == '`' {
+            chars.next();
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
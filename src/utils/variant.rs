@@ -1,45 +1,72 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
 use std::io::prelude::*;
-use std::io::Cursor;
+use std::io::{Cursor, Error, ErrorKind};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
 enum VariantType {
-    Unknown,
-    Float,
-    String,
-    Vec2,
-    Vec3,
-    Unsigned,
-    Signed,
+    Unknown = 0,
+    Float = 1,
+    String = 2,
+    Vec2 = 3,
+    Vec3 = 4,
+    Unsigned = 5,
+    Entity = 6,
+    Component = 7,
+    Rect = 8,
+    Signed = 9,
 }
 
-impl From<u8> for VariantType {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for VariantType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => VariantType::Unknown,
-            1 => VariantType::Float,
-            2 => VariantType::String,
-            3 => VariantType::Vec2,
-            4 => VariantType::Vec3,
-            5 => VariantType::Unsigned,
-            9 => VariantType::Signed,
-            _ => VariantType::Unknown,
+            0 => Ok(VariantType::Unknown),
+            1 => Ok(VariantType::Float),
+            2 => Ok(VariantType::String),
+            3 => Ok(VariantType::Vec2),
+            4 => Ok(VariantType::Vec3),
+            5 => Ok(VariantType::Unsigned),
+            6 => Ok(VariantType::Entity),
+            7 => Ok(VariantType::Component),
+            8 => Ok(VariantType::Rect),
+            9 => Ok(VariantType::Signed),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown variant type: {}", value),
+            )),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Variant {
     Float(f32),
     String(String),
     Vec2((f32, f32)),
     Vec3((f32, f32, f32)),
     Unsigned(u32),
+    Rect((f32, f32, f32, f32)),
     Signed(i32),
     Unknown,
 }
 
 impl Variant {
+    fn variant_type(&self) -> VariantType {
+        match self {
+            Variant::Float(_) => VariantType::Float,
+            Variant::String(_) => VariantType::String,
+            Variant::Vec2(_) => VariantType::Vec2,
+            Variant::Vec3(_) => VariantType::Vec3,
+            Variant::Unsigned(_) => VariantType::Unsigned,
+            Variant::Rect(_) => VariantType::Rect,
+            Variant::Signed(_) => VariantType::Signed,
+            Variant::Unknown => VariantType::Unknown,
+        }
+    }
+
     pub fn as_string(&self) -> String {
         match self {
             Variant::Float(value) => value.to_string(),
@@ -47,40 +74,86 @@ impl Variant {
             Variant::Vec2((x, y)) => format!("{}, {}", x, y),
             Variant::Vec3((x, y, z)) => format!("{}, {}, {}", x, y, z),
             Variant::Unsigned(value) => value.to_string(),
+            Variant::Rect((x, y, w, h)) => format!("{}, {}, {}, {}", x, y, w, h),
             Variant::Signed(value) => value.to_string(),
             Variant::Unknown => "Unknown".to_string(),
         }
     }
 
-    pub fn as_int32(&self) -> i32 {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Variant::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
         match self {
-            Variant::Signed(value) => *value,
-            _ => 0,
+            Variant::Float(value) => Some(*value),
+            _ => None,
         }
     }
 
-    pub fn as_vec2(&self) -> (f32, f32) {
+    pub fn as_uint32(&self) -> Option<u32> {
         match self {
-            Variant::Vec2(value) => *value,
-            _ => (0.0, 0.0),
+            Variant::Unsigned(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int32(&self) -> Option<i32> {
+        match self {
+            Variant::Signed(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec2(&self) -> Option<(f32, f32)> {
+        match self {
+            Variant::Vec2(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec3(&self) -> Option<(f32, f32, f32)> {
+        match self {
+            Variant::Vec3(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_rect(&self) -> Option<(f32, f32, f32, f32)> {
+        match self {
+            Variant::Rect(value) => Some(*value),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct VariantList {
     variants: Vec<Variant>,
 }
 
 impl VariantList {
-    pub fn deserialize(data: &[u8]) -> Result<Self, std::io::Error> {
+    pub fn new() -> Self {
+        Self {
+            variants: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, variant: Variant) {
+        self.variants.push(variant);
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
         let mut cursor = Cursor::new(data);
         let size = cursor.read_u8()?;
         let mut variants = Vec::with_capacity(size as usize);
 
         for _ in 0..size {
             let _index = cursor.read_u8()?;
-            let var_type: VariantType = cursor.read_u8()?.into();
+            let var_type = VariantType::try_from(cursor.read_u8()?)?;
 
             let variant = match var_type {
                 VariantType::Float => {
@@ -89,9 +162,17 @@ impl VariantList {
                 }
                 VariantType::String => {
                     let len = cursor.read_u32::<LittleEndian>()? as usize;
+                    let remaining = data.len() - cursor.position() as usize;
+                    if len > remaining {
+                        return Err(Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "string variant is longer than the remaining data",
+                        ));
+                    }
                     let mut buffer = vec![0; len];
                     cursor.read_exact(&mut buffer)?;
-                    let value = String::from_utf8(buffer).unwrap();
+                    let value = String::from_utf8(buffer)
+                        .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
                     Variant::String(value)
                 }
                 VariantType::Vec2 => {
@@ -109,10 +190,24 @@ impl VariantList {
                     let value = cursor.read_u32::<LittleEndian>()?;
                     Variant::Unsigned(value)
                 }
+                VariantType::Rect => {
+                    let x = cursor.read_f32::<LittleEndian>()?;
+                    let y = cursor.read_f32::<LittleEndian>()?;
+                    let w = cursor.read_f32::<LittleEndian>()?;
+                    let h = cursor.read_f32::<LittleEndian>()?;
+                    Variant::Rect((x, y, w, h))
+                }
                 VariantType::Signed => {
                     let value = cursor.read_i32::<LittleEndian>()?;
                     Variant::Signed(value)
                 }
+                // Entities and components are pointers on the client, they never go over the wire
+                VariantType::Entity | VariantType::Component => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("variant type {:?} can't be deserialized", var_type),
+                    ));
+                }
                 VariantType::Unknown => Variant::Unknown,
             };
 
@@ -122,7 +217,152 @@ impl VariantList {
         Ok(Self { variants })
     }
 
+    // Count and indices are a single byte on the wire, longer lists can't be written
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let size = u8::try_from(self.variants.len()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("too many variants: {}", self.variants.len()),
+            )
+        })?;
+        let mut data = Vec::new();
+        data.push(size);
+
+        for (index, variant) in (0..size).zip(self.variants.iter()) {
+            data.push(index);
+            data.push(variant.variant_type() as u8);
+
+            // Writing into a Vec can't fail
+            match variant {
+                Variant::Float(value) => data.write_f32::<LittleEndian>(*value).unwrap(),
+                Variant::String(value) => {
+                    let len = u32::try_from(value.len()).map_err(|_| {
+                        Error::new(ErrorKind::InvalidInput, "string variant is too long")
+                    })?;
+                    data.write_u32::<LittleEndian>(len).unwrap();
+                    data.extend_from_slice(value.as_bytes());
+                }
+                Variant::Vec2((x, y)) => {
+                    data.write_f32::<LittleEndian>(*x).unwrap();
+                    data.write_f32::<LittleEndian>(*y).unwrap();
+                }
+                Variant::Vec3((x, y, z)) => {
+                    data.write_f32::<LittleEndian>(*x).unwrap();
+                    data.write_f32::<LittleEndian>(*y).unwrap();
+                    data.write_f32::<LittleEndian>(*z).unwrap();
+                }
+                Variant::Unsigned(value) => data.write_u32::<LittleEndian>(*value).unwrap(),
+                Variant::Rect((x, y, w, h)) => {
+                    data.write_f32::<LittleEndian>(*x).unwrap();
+                    data.write_f32::<LittleEndian>(*y).unwrap();
+                    data.write_f32::<LittleEndian>(*w).unwrap();
+                    data.write_f32::<LittleEndian>(*h).unwrap();
+                }
+                Variant::Signed(value) => data.write_i32::<LittleEndian>(*value).unwrap(),
+                Variant::Unknown => {}
+            }
+        }
+
+        Ok(data)
+    }
+
     pub fn get(&self, index: usize) -> Option<&Variant> {
         self.variants.get(index)
     }
+
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Variant> {
+        self.variants.iter()
+    }
+}
+
+impl From<Vec<Variant>> for VariantList {
+    fn from(variants: Vec<Variant>) -> Self {
+        Self { variants }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_variant() -> Vec<Variant> {
+        vec![
+            Variant::Float(1.5),
+            Variant::String("OnConsoleMessage".to_string()),
+            Variant::String(String::new()),
+            Variant::Vec2((3.0, -4.0)),
+            Variant::Vec3((1.0, 2.0, 3.0)),
+            Variant::Unsigned(u32::MAX),
+            Variant::Rect((0.0, 1.0, 32.0, 64.0)),
+            Variant::Signed(-17091),
+            Variant::Unknown,
+        ]
+    }
+
+    #[test]
+    fn round_trips_every_variant_type() {
+        let list = VariantList::from(every_variant());
+        let data = list.serialize().unwrap();
+        let parsed = VariantList::deserialize(&data).unwrap();
+        assert_eq!(parsed, list);
+    }
+
+    #[test]
+    fn round_trips_each_variant_alone() {
+        for variant in every_variant() {
+            let list = VariantList::from(vec![variant.clone()]);
+            let parsed = VariantList::deserialize(&list.serialize().unwrap()).unwrap();
+            assert_eq!(parsed.get(0), Some(&variant));
+            assert_eq!(parsed.len(), 1);
+        }
+    }
+
+    #[test]
+    fn round_trips_empty_list() {
+        let data = VariantList::new().serialize().unwrap();
+        assert_eq!(data, vec![0]);
+        assert!(VariantList::deserialize(&data).unwrap().is_empty());
+    }
+
+    #[test]
+    fn serializes_the_largest_list() {
+        let list = VariantList::from(vec![Variant::Unsigned(1); 255]);
+        let data = list.serialize().unwrap();
+        assert_eq!(data[0], 255);
+        assert_eq!(VariantList::deserialize(&data).unwrap(), list);
+    }
+
+    #[test]
+    fn rejects_more_than_255_variants() {
+        let list = VariantList::from(vec![Variant::Unknown; 256]);
+        let err = list.serialize().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rejects_every_truncation() {
+        let data = VariantList::from(every_variant()).serialize().unwrap();
+        for len in 0..data.len() {
+            let err = VariantList::deserialize(&data[..len]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof, "truncated to {}", len);
+        }
+    }
+
+    #[test]
+    fn rejects_string_longer_than_remaining_bytes() {
+        // One string variant claiming 100 bytes with only 3 after it
+        let mut data = vec![1, 0, VariantType::String as u8];
+        data.extend_from_slice(&100u32.to_le_bytes());
+        data.extend_from_slice(b"abc");
+        let err = VariantList::deserialize(&data).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
 }
@@ -0,0 +1,97 @@
+// Shared by the mock server tests. Each test lives in its own binary, ENet can only be
+// initialized once per process and the mock server and the bot's reactor both need it.
+
+use std::net::{Ipv4Addr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enet::Enet;
+use gtitem_r::structs::ItemDatabase;
+use mori::bot::{self, reactor::ReactorPool, Bot};
+use mori::mock_server;
+use mori::types::bot_state::BotState;
+use mori::types::e_login_method::ELoginMethod;
+use mori::types::endpoints::Endpoints;
+use mori::types::reconnect_policy::ReconnectPolicy;
+
+pub struct Run {
+    pub result: Result<(), String>,
+    pub bot: Arc<Mutex<Bot>>,
+    // Every state the bot was seen in while the script ran, in order
+    pub states: Vec<BotState>,
+}
+
+// Plays the script against a freshly logged in bot, returns once the script is done
+pub fn run_script(script_path: &str, http_port: u16, reconnect_policy: ReconnectPolicy) -> Run {
+    let enet = Enet::new().expect("Failed to initialize ENet");
+    let mock = {
+        let enet = enet.clone();
+        let script_path = script_path.to_string();
+        thread::spawn(move || mock_server::run_with_enet(&enet, &script_path))
+    };
+    wait_for_http(http_port);
+
+    let pool = ReactorPool::with_enet(enet, 1).unwrap();
+    let reactor = pool.least_loaded();
+    let bot_mutex = Arc::new(Mutex::new(mock_bot(http_port, reconnect_policy)));
+    {
+        let bot_mutex = Arc::clone(&bot_mutex);
+        thread::spawn(move || bot::login(bot_mutex, reactor));
+    }
+
+    let mut states = vec![BotState::Idle];
+    loop {
+        let finished = mock.is_finished();
+        let state = bot_mutex.lock().unwrap().state.current();
+        if states.last() != Some(&state) {
+            states.push(state);
+        }
+        if finished {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    Run {
+        result: mock.join().unwrap(),
+        bot: bot_mutex,
+        states,
+    }
+}
+
+// True if expected shows up in states in the same order, other states may come in between
+pub fn went_through(states: &[BotState], expected: &[BotState]) -> bool {
+    let mut states = states.iter();
+    expected
+        .iter()
+        .all(|expected| states.any(|state| state == expected))
+}
+
+fn mock_bot(http_port: u16, reconnect_policy: ReconnectPolicy) -> Bot {
+    let mut bot = Bot::new(
+        "mock".to_string(),
+        "mock".to_string(),
+        String::new(),
+        ELoginMethod::LEGACY,
+        reconnect_policy,
+        None,
+        Arc::new(ItemDatabase::new()),
+    );
+    let base = format!("http://127.0.0.1:{}", http_port);
+    bot.info.endpoints = Endpoints {
+        server_data: format!("{}/growtopia/server_data.php", base),
+        login: base.clone(),
+        ubisoft: base,
+    };
+    bot
+}
+
+// The mock binds its HTTP server on its own thread, the bot's first request can't beat it
+fn wait_for_http(http_port: u16) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while TcpStream::connect((Ipv4Addr::LOCALHOST, http_port)).is_err() {
+        assert!(Instant::now() < deadline, "mock HTTP server didn't start");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
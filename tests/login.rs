@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+
+use gtitem_r::structs::ItemDatabase;
+use mori::bot::{self, Bot};
+use mori::types::bot_state::BotState;
+use mori::types::e_login_method::ELoginMethod;
+use mori::types::endpoints::Endpoints;
+use mori::types::reconnect_policy::ReconnectPolicy;
+use mori::utils::http::{HttpClient, HttpError, Request};
+
+const LOGIN: &str = "https://login.test";
+const SERVER_DATA: &str = "server|127.0.0.1\nport|17091\ntype|1\nmeta|meta-abc\nRTENDMARKERBS1001";
+const GROWID_PAGE: &str = r#"<form><input name="_token" type="hidden" value="csrf-123"></form>"#;
+
+// Answers each request with the first canned body whose path is in the URL
+struct StubClient {
+    responses: Vec<(&'static str, String)>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl StubClient {
+    fn new(responses: Vec<(&'static str, String)>) -> StubClient {
+        StubClient {
+            responses,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl HttpClient for StubClient {
+    fn send(&self, request: Request) -> Result<String, HttpError> {
+        self.requests.lock().unwrap().push(request.url.clone());
+        self.responses
+            .iter()
+            .find(|(path, _)| request.url.contains(path))
+            .map(|(_, body)| body.clone())
+            .ok_or(HttpError::Status(404, request.url))
+    }
+}
+
+fn dashboard() -> String {
+    format!(
+        r#"<a href="{0}/apple/redirect?token=a1">Apple</a>
+<a href="{0}/google/redirect?token=g1">Google</a>
+<a href="{0}/player/growid/login?token=l1">GrowID</a>"#,
+        LOGIN
+    )
+}
+
+fn legacy_responses(validate: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("server_data.php", SERVER_DATA.to_string()),
+        ("/player/login/dashboard", dashboard()),
+        ("/player/growid/login?token", GROWID_PAGE.to_string()),
+        ("/player/growid/login/validate", validate.to_string()),
+    ]
+}
+
+fn test_bot(method: ELoginMethod, http: Arc<StubClient>) -> Arc<Mutex<Bot>> {
+    let mut bot = Bot::new(
+        "growid".to_string(),
+        "secret".to_string(),
+        String::new(),
+        method,
+        ReconnectPolicy::default(),
+        None,
+        Arc::new(ItemDatabase::new()),
+    );
+    bot.info.http = http;
+    bot.info.endpoints = Endpoints {
+        server_data: "https://www.test/server_data.php".to_string(),
+        login: LOGIN.to_string(),
+        ubisoft: "https://ubi.test".to_string(),
+    };
+    Arc::new(Mutex::new(bot))
+}
+
+#[test]
+fn legacy_login_gets_a_token() {
+    let http = Arc::new(StubClient::new(legacy_responses(
+        r#"{"status":"success","token":"ltoken-123"}"#,
+    )));
+    let bot_mutex = test_bot(ELoginMethod::LEGACY, Arc::clone(&http));
+
+    bot::authenticate(&bot_mutex).unwrap();
+
+    let bot = bot_mutex.lock().unwrap();
+    assert_eq!(bot.info.token, "ltoken-123");
+    assert_eq!(bot.info.login_info.meta, "meta-abc");
+    assert_eq!(bot.info.oauth_links.len(), 3);
+    assert_eq!(bot.state.current(), BotState::Authenticating);
+    assert_eq!(
+        *http.requests.lock().unwrap(),
+        vec![
+            "https://www.test/server_data.php".to_string(),
+            format!("{}/player/login/dashboard", LOGIN),
+            format!("{}/player/growid/login?token=l1", LOGIN),
+            format!("{}/player/growid/login/validate", LOGIN),
+        ]
+    );
+}
+
+#[test]
+fn ubisoft_login_gets_a_token() {
+    let http = Arc::new(StubClient::new(vec![
+        ("server_data.php", SERVER_DATA.to_string()),
+        (
+            "/v3/profiles/sessions",
+            r#"{"ticket":"ubi-ticket","twoFactorAuthenticationTicket":null}"#.to_string(),
+        ),
+        (
+            "/player/login/dashboard",
+            r#"{"status":"success","token":"ubi-token"}"#.to_string(),
+        ),
+    ]));
+    let bot_mutex = test_bot(ELoginMethod::UBISOFT, http);
+
+    bot::authenticate(&bot_mutex).unwrap();
+
+    assert_eq!(bot_mutex.lock().unwrap().info.token, "ubi-token");
+}
+
+#[test]
+fn malformed_token_response_is_an_error() {
+    let http = Arc::new(StubClient::new(legacy_responses("<html>oops</html>")));
+    let bot_mutex = test_bot(ELoginMethod::LEGACY, http);
+    let token = bot_mutex.lock().unwrap().info.token.clone();
+
+    let err = bot::authenticate(&bot_mutex).unwrap_err();
+
+    assert!(err.contains("invalid response"), "{}", err);
+    assert_eq!(bot_mutex.lock().unwrap().info.token, token);
+}
+
+#[test]
+fn token_response_without_a_token_is_an_error() {
+    let http = Arc::new(StubClient::new(legacy_responses(
+        r#"{"status":"error","message":"nope"}"#,
+    )));
+    let bot_mutex = test_bot(ELoginMethod::LEGACY, http);
+
+    let err = bot::authenticate(&bot_mutex).unwrap_err();
+
+    assert!(err.contains("no token"), "{}", err);
+}
+
+#[test]
+fn growid_page_without_a_csrf_token_is_an_error() {
+    let mut responses = legacy_responses(r#"{"token":"unused"}"#);
+    responses[2].1 = "<html>maintenance</html>".to_string();
+    let bot_mutex = test_bot(ELoginMethod::LEGACY, Arc::new(StubClient::new(responses)));
+
+    let err = bot::authenticate(&bot_mutex).unwrap_err();
+
+    assert!(err.contains("_token"), "{}", err);
+}
+
+#[test]
+fn dashboard_without_links_is_an_error() {
+    let mut responses = legacy_responses(r#"{"token":"unused"}"#);
+    responses[1].1 = "<html></html>".to_string();
+    let bot_mutex = test_bot(ELoginMethod::LEGACY, Arc::new(StubClient::new(responses)));
+
+    let err = bot::authenticate(&bot_mutex).unwrap_err();
+
+    assert!(err.contains("login link"), "{}", err);
+}
+
+#[test]
+fn server_error_is_an_error() {
+    let http = Arc::new(StubClient::new(vec![(
+        "server_data.php",
+        SERVER_DATA.to_string(),
+    )]));
+    let bot_mutex = test_bot(ELoginMethod::LEGACY, http);
+
+    let err = bot::authenticate(&bot_mutex).unwrap_err();
+
+    assert!(err.contains("OAuth links"), "{}", err);
+}
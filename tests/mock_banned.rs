@@ -0,0 +1,25 @@
+mod common;
+
+use common::{run_script, went_through};
+use mori::types::bot_state::BotState;
+use mori::types::reconnect_policy::{DisconnectReason, ReconnectPolicy};
+
+#[test]
+fn stops_when_banned() {
+    let run = run_script(
+        "assets/mock_scripts/banned.json",
+        8180,
+        ReconnectPolicy::default(),
+    );
+    run.result.unwrap();
+
+    assert!(
+        went_through(&run.states, &[BotState::Connecting, BotState::Banned]),
+        "{:?}",
+        run.states
+    );
+    let bot = run.bot.lock().unwrap();
+    assert_eq!(bot.state.current(), BotState::Banned);
+    assert_eq!(bot.state.disconnect_reason, Some(DisconnectReason::Banned));
+    assert_eq!(bot.state.reconnect_attempts, 0);
+}
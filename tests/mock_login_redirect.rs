@@ -0,0 +1,36 @@
+mod common;
+
+use common::{run_script, went_through};
+use mori::types::bot_state::BotState;
+use mori::types::reconnect_policy::ReconnectPolicy;
+
+#[test]
+fn logs_in_through_a_redirect() {
+    let run = run_script(
+        "assets/mock_scripts/login_redirect.json",
+        8080,
+        ReconnectPolicy::default(),
+    );
+    run.result.unwrap();
+
+    assert!(
+        went_through(
+            &run.states,
+            &[
+                BotState::FetchingServerData,
+                BotState::Authenticating,
+                BotState::Connecting,
+                BotState::Redirecting,
+                BotState::InGame,
+                BotState::InWorld,
+            ]
+        ),
+        "{:?}",
+        run.states
+    );
+    let bot = run.bot.lock().unwrap();
+    assert_eq!(bot.info.token, "mock-token");
+    assert_eq!(bot.info.login_info.uuid, "mock-uuid");
+    assert_eq!(bot.state.net_id, 3);
+    assert!(bot.players.contains_key(&4));
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{run_script, went_through};
+use mori::types::bot_state::BotState;
+use mori::types::reconnect_policy::{DisconnectReason, ReconnectPolicy};
+
+#[test]
+fn reconnects_when_the_server_is_full() {
+    let policy = ReconnectPolicy {
+        base_delay_ms: 200,
+        jitter: 0.0,
+        ..Default::default()
+    };
+    let run = run_script("assets/mock_scripts/server_full.json", 8280, policy);
+    run.result.unwrap();
+
+    assert!(
+        went_through(
+            &run.states,
+            &[
+                BotState::Connecting,
+                BotState::Reconnecting,
+                BotState::FetchingServerData,
+                BotState::Connecting,
+            ]
+        ),
+        "{:?}",
+        run.states
+    );
+    let bot = run.bot.lock().unwrap();
+    assert_eq!(
+        bot.state.disconnect_reason,
+        Some(DisconnectReason::ServerFull)
+    );
+    assert_eq!(bot.state.reconnect_attempts, 1);
+}